@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::image_utils::calculate_image_difference_luma;
+
+use super::{AdaptiveFps, CapturedFrame};
+
+/// Slowest rate an adaptive capture drops to, whatever the settings say
+const FLOOR_FPS: f32 = 0.001;
+
+/// Current rate of an adaptive capture, see [`AdaptiveFps`].
+pub(crate) struct AdaptiveRate {
+    settings: AdaptiveFps,
+    fps: f32,
+    // Luma of the last frame of every stream
+    previous: HashMap<Option<String>, Vec<u8>>,
+}
+
+impl AdaptiveRate {
+    pub fn new(settings: AdaptiveFps) -> Self {
+        let fps = settings.get_min_fps();
+        Self {
+            settings,
+            fps,
+            previous: HashMap::new(),
+        }
+    }
+
+    /// Whether any stream changed noticeably since its previous frame.
+    pub fn detect_change(&mut self, frames: &[CapturedFrame]) -> bool {
+        let mut changed = false;
+        for frame in frames {
+            let luma = frame.image.to_luma8().into_raw();
+            let difference = match self.previous.get(&frame.stream_name()) {
+                Some(previous) => calculate_image_difference_luma(&luma, previous),
+                None => 1.0,
+            };
+            changed |= difference > self.settings.get_threshold();
+            self.previous.insert(frame.stream_name(), luma);
+        }
+        changed
+    }
+
+    /// Time until the next capture: right away at the maximum rate after a change, otherwise
+    /// the rate halves on every static frame down to the idle rate.
+    pub fn next_interval(&mut self, changed: bool) -> Duration {
+        self.fps = if changed {
+            self.settings.get_max_fps()
+        } else {
+            (self.fps / 2.0).max(self.settings.get_min_fps())
+        }
+        .max(FLOOR_FPS);
+        Duration::from_secs_f32(1.0 / self.fps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_ramps_up_on_change_and_decays_to_idle() {
+        let mut rate = AdaptiveRate::new(AdaptiveFps::new(Some(0.5), Some(4.0), None));
+        assert_eq!(rate.next_interval(true), Duration::from_millis(250));
+        assert_eq!(rate.next_interval(false), Duration::from_millis(500));
+        assert_eq!(rate.next_interval(false), Duration::from_secs(1));
+        assert_eq!(rate.next_interval(false), Duration::from_secs(2));
+        assert_eq!(rate.next_interval(false), Duration::from_secs(2));
+        assert_eq!(rate.next_interval(true), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_rate_is_clamped_for_invalid_settings() {
+        let mut rate = AdaptiveRate::new(AdaptiveFps::new(Some(0.0), Some(-1.0), None));
+        assert_eq!(rate.next_interval(true).as_secs_f32().round(), 1000.0);
+        assert_eq!(rate.next_interval(false).as_secs_f32().round(), 1000.0);
+
+        assert!(AdaptiveFps::new(Some(0.0), None, None).validate().is_err());
+        assert!(AdaptiveFps::new(None, Some(-2.0), None).validate().is_err());
+        assert!(AdaptiveFps::new(Some(2.0), Some(1.0), None).validate().is_err());
+        assert!(AdaptiveFps::new(Some(0.2), Some(5.0), None).validate().is_ok());
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use super::CapturedFrame;
+
+/// A captured frame shared by all consumers of the pipeline.
+pub type SharedFrame = Arc<CapturedFrame>;
+
+/// What happens to a frame when a consumer's queue is full.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum BackpressurePolicy {
+    /// Capturing waits until the consumer catches up, nothing is lost
+    #[default]
+    Block,
+    /// The oldest queued frame makes room for the new one
+    DropOldest,
+    /// The new frame is dropped
+    DropNewest,
+    /// Only the latest frame of every stream is kept
+    CoalesceLatest,
+}
+
+impl std::str::FromStr for BackpressurePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "block" => Ok(BackpressurePolicy::Block),
+            "drop-oldest" => Ok(BackpressurePolicy::DropOldest),
+            "drop-newest" => Ok(BackpressurePolicy::DropNewest),
+            "coalesce" | "coalesce-latest" | "latest" => Ok(BackpressurePolicy::CoalesceLatest),
+            _ => bail!("Unknown backpressure policy {:?}", s),
+        }
+    }
+}
+
+/// Counters of one consumer of a [`FrameFanout`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ConsumerStats {
+    pub name: String,
+    pub policy: BackpressurePolicy,
+    /// Frames the consumer received
+    pub delivered: u64,
+    /// Frames dropped or coalesced away because the consumer fell behind
+    pub dropped: u64,
+    /// Times capturing had to wait for the consumer
+    pub lagged: u64,
+    /// Frames waiting for the consumer right now
+    pub queued: usize,
+}
+
+#[derive(Default)]
+struct QueueState {
+    frames: VecDeque<SharedFrame>,
+    closed: bool,
+    receiver_gone: bool,
+}
+
+struct ConsumerQueue {
+    name: String,
+    policy: BackpressurePolicy,
+    capacity: usize,
+    state: Mutex<QueueState>,
+    frame_added: Notify,
+    frame_taken: Notify,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    lagged: AtomicU64,
+}
+
+impl ConsumerQueue {
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Queues `frame` according to the policy, `false` once the receiver is gone.
+    async fn push(&self, frame: SharedFrame) -> bool {
+        let mut waited = false;
+        loop {
+            {
+                let mut state = self.lock();
+                if state.receiver_gone {
+                    return false;
+                }
+
+                if self.policy == BackpressurePolicy::CoalesceLatest {
+                    // Replace the queued frame of the same stream, other streams keep theirs
+                    let stream_name = frame.stream_name();
+                    let before = state.frames.len();
+                    state.frames.retain(|queued| queued.stream_name() != stream_name);
+                    if before > state.frames.len() {
+                        self.add_dropped((before - state.frames.len()) as u64);
+                    }
+                }
+
+                if state.frames.len() >= self.capacity {
+                    match self.policy {
+                        BackpressurePolicy::Block => {}
+                        BackpressurePolicy::DropOldest | BackpressurePolicy::CoalesceLatest => {
+                            state.frames.pop_front();
+                            self.add_dropped(1);
+                        }
+                        BackpressurePolicy::DropNewest => {
+                            self.add_dropped(1);
+                            return true;
+                        }
+                    }
+                }
+
+                if state.frames.len() < self.capacity {
+                    state.frames.push_back(frame);
+                    drop(state);
+                    self.frame_added.notify_one();
+                    return true;
+                }
+            }
+
+            if !waited {
+                waited = true;
+                self.lagged.fetch_add(1, Ordering::Relaxed);
+                log::debug!("Waiting for consumer {} to catch up", self.name);
+            }
+            self.frame_taken.notified().await;
+        }
+    }
+
+    fn add_dropped(&self, frames: u64) {
+        self.dropped.fetch_add(frames, Ordering::Relaxed);
+        log::debug!("Consumer {} fell behind, dropped {} frames", self.name, frames);
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.frame_added.notify_one();
+    }
+
+    fn stats(&self) -> ConsumerStats {
+        ConsumerStats {
+            name: self.name.clone(),
+            policy: self.policy,
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            lagged: self.lagged.load(Ordering::Relaxed),
+            queued: self.lock().frames.len(),
+        }
+    }
+}
+
+/// Hands every captured frame to all consumers, each with its own queue and [`BackpressurePolicy`].
+///
+/// Dropping the fanout closes it: consumers still get the queued frames, then `recv` returns `None`.
+#[derive(Default)]
+pub struct FrameFanout {
+    queues: Vec<Arc<ConsumerQueue>>,
+}
+
+impl FrameFanout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a consumer, frames sent before it subscribed are not delivered to it.
+    pub fn subscribe(&mut self, name: &str, policy: BackpressurePolicy, capacity: usize) -> FrameReceiver {
+        let queue = Arc::new(ConsumerQueue {
+            name: name.to_string(),
+            policy,
+            capacity: capacity.max(1),
+            state: Mutex::new(QueueState::default()),
+            frame_added: Notify::new(),
+            frame_taken: Notify::new(),
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            lagged: AtomicU64::new(0),
+        });
+        self.queues.push(queue.clone());
+        FrameReceiver { queue }
+    }
+
+    /// Sends `frame` to every consumer, fails once all consumers are gone.
+    pub async fn send(&self, frame: CapturedFrame) -> Result<()> {
+        let frame = Arc::new(frame);
+        let mut delivered = false;
+        for queue in &self.queues {
+            delivered |= queue.push(frame.clone()).await;
+        }
+        if !delivered {
+            return Err(anyhow::anyhow!("No consumer left for frame {}", frame.frame_number));
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> FanoutStats {
+        FanoutStats { queues: self.queues.clone() }
+    }
+}
+
+impl Drop for FrameFanout {
+    fn drop(&mut self) {
+        for queue in &self.queues {
+            queue.close();
+        }
+    }
+}
+
+/// Read-only view of the counters of a [`FrameFanout`], stays valid after it is closed.
+#[derive(Clone, Default)]
+pub struct FanoutStats {
+    queues: Vec<Arc<ConsumerQueue>>,
+}
+
+impl FanoutStats {
+    pub fn consumers(&self) -> Vec<ConsumerStats> {
+        self.queues.iter().map(|queue| queue.stats()).collect()
+    }
+
+    pub fn log_summary(&self) {
+        for consumer in self.consumers() {
+            log::info!(
+                "Consumer {} ({:?}): {} frames delivered, {} dropped, lagged {} times",
+                consumer.name,
+                consumer.policy,
+                consumer.delivered,
+                consumer.dropped,
+                consumer.lagged
+            );
+        }
+    }
+}
+
+/// Consumer side of a [`FrameFanout`].
+pub struct FrameReceiver {
+    queue: Arc<ConsumerQueue>,
+}
+
+impl FrameReceiver {
+    /// Next frame, `None` once the fanout is closed and every queued frame was received.
+    pub async fn recv(&mut self) -> Option<SharedFrame> {
+        loop {
+            {
+                let mut state = self.queue.lock();
+                if let Some(frame) = state.frames.pop_front() {
+                    drop(state);
+                    self.queue.delivered.fetch_add(1, Ordering::Relaxed);
+                    self.queue.frame_taken.notify_one();
+                    return Some(frame);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.queue.frame_added.notified().await;
+        }
+    }
+}
+
+impl Drop for FrameReceiver {
+    fn drop(&mut self) {
+        let mut state = self.queue.lock();
+        state.receiver_gone = true;
+        state.frames.clear();
+        drop(state);
+        // A capture blocked on this consumer can go on
+        self.queue.frame_taken.notify_one();
+    }
+}
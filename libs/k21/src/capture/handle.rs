@@ -0,0 +1,210 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tokio::sync::watch;
+
+use super::{ConsumerStats, FanoutStats};
+
+/// Requested state of a running capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureState {
+    Running,
+    Paused,
+    Stopped,
+}
+
+/// Snapshot of a capture, see [`CaptureHandle::status`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureStatus {
+    pub state: CaptureState,
+    /// Frames of all streams sent down the pipeline
+    pub frames_captured: u64,
+    pub capture_errors: u64,
+    pub elapsed: Duration,
+    /// Frames delivered to and dropped by every consumer of the pipeline
+    pub consumers: Vec<ConsumerStats>,
+    /// Waiting for the capture schedule to allow capturing again
+    pub outside_schedule: bool,
+}
+
+pub(crate) struct CaptureCounters {
+    frames_captured: AtomicU64,
+    capture_errors: AtomicU64,
+    // The capture loop waits for a resume, no more frames are captured
+    paused: AtomicBool,
+    fanout: OnceLock<FanoutStats>,
+    outside_schedule: watch::Sender<bool>,
+}
+
+impl CaptureCounters {
+    fn new(outside_schedule: watch::Sender<bool>) -> Self {
+        Self {
+            frames_captured: AtomicU64::new(0),
+            capture_errors: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            fanout: OnceLock::new(),
+            outside_schedule,
+        }
+    }
+}
+
+impl Default for CaptureCounters {
+    fn default() -> Self {
+        Self::new(watch::channel(false).0)
+    }
+}
+
+/// Capture loop side of a [`CaptureHandle`].
+#[derive(Clone)]
+pub(crate) struct CaptureControl {
+    state_rx: watch::Receiver<CaptureState>,
+    counters: Arc<CaptureCounters>,
+}
+
+impl CaptureControl {
+    /// Control of a capture nobody holds a handle for, it runs until its duration is over.
+    /// Whether it waits for its schedule is sent to `outside_schedule`.
+    pub fn detached(outside_schedule: watch::Sender<bool>) -> Self {
+        let (_, state_rx) = watch::channel(CaptureState::Running);
+        Self {
+            state_rx,
+            counters: Arc::new(CaptureCounters::new(outside_schedule)),
+        }
+    }
+
+    /// Waits as long as the capture is paused, `false` once it should stop.
+    pub async fn wait_while_paused(&mut self) -> bool {
+        loop {
+            let state = *self.state_rx.borrow_and_update();
+            self.counters.paused.store(state == CaptureState::Paused, Ordering::Relaxed);
+            match state {
+                CaptureState::Running => return true,
+                CaptureState::Stopped => return false,
+                CaptureState::Paused => {}
+            }
+            // Without a handle nobody can resume, so keep capturing
+            if self.state_rx.changed().await.is_err() {
+                return true;
+            }
+        }
+    }
+
+    /// Completes once the capture should stop, never for a detached capture.
+    pub async fn stopped(&mut self) {
+        loop {
+            if *self.state_rx.borrow_and_update() == CaptureState::Stopped {
+                return;
+            }
+            if self.state_rx.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    pub fn add_frames(&self, frames: usize) {
+        self.counters.frames_captured.fetch_add(frames as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_error(&self) {
+        self.counters.capture_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Reports the consumer counters of `stats` in [`CaptureHandle::status`].
+    pub fn track_fanout(&self, stats: FanoutStats) {
+        let _ = self.counters.fanout.set(stats);
+    }
+
+    /// Records whether the capture waits for its schedule, `true` if that changed.
+    pub fn set_outside_schedule(&self, outside: bool) -> bool {
+        self.counters.outside_schedule.send_if_modified(|current| {
+            let changed = *current != outside;
+            *current = outside;
+            changed
+        })
+    }
+
+    /// Follows [`CaptureControl::set_outside_schedule`], e.g. to close video chunks.
+    pub fn outside_schedule(&self) -> watch::Receiver<bool> {
+        self.counters.outside_schedule.subscribe()
+    }
+}
+
+/// Controls a capture started with [`super::start_capture`].
+///
+/// Dropping the handle leaves the capture running until its duration is over.
+pub struct CaptureHandle {
+    state_tx: watch::Sender<CaptureState>,
+    counters: Arc<CaptureCounters>,
+    started: Instant,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl CaptureHandle {
+    pub(crate) fn new(task: impl FnOnce(CaptureControl) -> tokio::task::JoinHandle<()>) -> Self {
+        let (state_tx, state_rx) = watch::channel(CaptureState::Running);
+        let counters = Arc::new(CaptureCounters::default());
+        let task = task(CaptureControl {
+            state_rx,
+            counters: counters.clone(),
+        });
+
+        Self {
+            state_tx,
+            counters,
+            started: Instant::now(),
+            task,
+        }
+    }
+
+    /// Stops grabbing frames until [`CaptureHandle::resume`], the current video chunk stays open.
+    pub fn pause(&self) {
+        self.set_state(CaptureState::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.set_state(CaptureState::Running);
+    }
+
+    /// Stops capturing and waits until the final video chunks are written.
+    pub async fn stop(self) -> Result<()> {
+        self.set_state(CaptureState::Stopped);
+        self.wait().await
+    }
+
+    /// Waits for the capture to end by itself, e.g. when its duration is over.
+    pub async fn wait(self) -> Result<()> {
+        self.task.await?;
+        Ok(())
+    }
+
+    /// Current state, a pause only shows once the capture loop stopped grabbing frames.
+    pub fn status(&self) -> CaptureStatus {
+        let state = match *self.state_tx.borrow() {
+            _ if self.task.is_finished() => CaptureState::Stopped,
+            CaptureState::Paused if !self.counters.paused.load(Ordering::Relaxed) => CaptureState::Running,
+            state => state,
+        };
+
+        CaptureStatus {
+            state,
+            frames_captured: self.counters.frames_captured.load(Ordering::Relaxed),
+            capture_errors: self.counters.capture_errors.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed(),
+            consumers: self.counters.fanout.get().map(FanoutStats::consumers).unwrap_or_default(),
+            outside_schedule: *self.counters.outside_schedule.borrow(),
+        }
+    }
+
+    fn set_state(&self, state: CaptureState) {
+        // A stopped capture stays stopped
+        self.state_tx.send_if_modified(|current| {
+            let changed = *current != state && *current != CaptureState::Stopped;
+            if changed {
+                *current = state;
+            }
+            changed
+        });
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{EncoderSettings, MonitorInfo, RegionInfo, ScreenCaptureConfig, WindowInfo};
+
+/// When a frame of a chunk was captured.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FrameTime {
+    pub frame_number: u64,
+    pub captured_at: DateTime<Local>,
+}
+
+/// Sidecar of a recorded video chunk, written next to it as `<chunk>.json`.
+///
+/// Frames are listed in the order they were written to the video, so the n-th sample of the
+/// video belongs to the n-th entry of `frames`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkManifest {
+    /// File name of the video chunk
+    pub video: String,
+    /// SHA-256 of the video chunk, hex encoded
+    pub sha256: String,
+    pub stream: Option<String>,
+    pub chunk_number: u64,
+    /// Size of the captured frames
+    pub width: u32,
+    pub height: u32,
+    /// Size of the video, frames of an odd size are padded or cropped to an even one
+    pub coded_width: Option<u32>,
+    pub coded_height: Option<u32>,
+    pub monitor: Option<MonitorInfo>,
+    pub window: Option<WindowInfo>,
+    pub region: Option<RegionInfo>,
+    pub config: ScreenCaptureConfig,
+    pub encoder: EncoderSettings,
+    pub frames: Vec<FrameTime>,
+    /// Frame numbers the stream missed, because capturing or encoding them failed
+    pub dropped_frames: Vec<u64>,
+}
+
+impl ChunkManifest {
+    pub fn path_for(video_path: &Path) -> PathBuf {
+        video_path.with_extension("json")
+    }
+
+    pub fn write(&self, video_path: &Path) -> Result<()> {
+        let file = File::create(Self::path_for(video_path))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Reads the manifest of a video chunk, `Ok(None)` if it has none.
+    pub fn read_for(video_path: &Path) -> Result<Option<Self>> {
+        let path = Self::path_for(video_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let manifest = serde_json::from_reader(File::open(path)?)?;
+        Ok(Some(manifest))
+    }
+
+    /// Whether the manifest was written for exactly this video content.
+    pub fn matches(&self, video: &[u8]) -> bool {
+        self.sha256 == sha256_hex(video)
+    }
+
+    pub fn frame(&self, sample_index: usize) -> Option<&FrameTime> {
+        self.frames.get(sample_index)
+    }
+}
+
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+pub(crate) fn file_sha256_hex(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -1,12 +1,76 @@
 mod utils;
 pub use utils::capture;
 pub use utils::spawn_screenshot_task;
+pub use utils::spawn_frame_source_task;
 pub use utils::capture_with_stdout;
+pub use utils::start_capture;
+pub use utils::start_capture_with_stdout;
 pub use utils::handle_captured_frames;
+pub use utils::RECORDER_CONSUMER;
+
+mod mp4_writer;
+pub(crate) use mp4_writer::{MP4_EPOCH_OFFSET, START_TIME_BOX};
+
+mod adaptive;
+
+mod fanout;
+pub use fanout::{BackpressurePolicy, ConsumerStats, FanoutStats, FrameFanout, FrameReceiver, SharedFrame};
+
+mod handle;
+pub use handle::{CaptureHandle, CaptureState, CaptureStatus};
+
+mod manifest;
+pub use manifest::{ChunkManifest, FrameTime};
+
+mod protocol;
+pub use protocol::{
+    decode_payload, encode_frame, stream_header, FrameDecoder, FrameEncoder, FrameEncoding, FrameHeader, PixelFormat,
+    FRAME_MAGIC, PROTOCOL_VERSION, STREAM_MAGIC,
+};
+
+mod rawvideo;
+pub use rawvideo::{RawPixelFormat, RawVideoDecoder};
+
+mod redact;
+pub use redact::{Redaction, RedactionReason, RedactionRecord, Redactor};
+
+mod retention;
+pub use retention::{enforce_retention, RetentionReport, DOWNSAMPLED_DIR};
+
+mod schedule;
+pub use schedule::Schedule;
 
 mod screen_record;
 pub use screen_record::ScreenCapturer;
 
+mod source;
+pub use source::{FrameSource, ImageDirectorySource, MonitorSource, Mp4FileSource, SyntheticSource, WindowSource};
+
+mod y4m;
+pub use y4m::{ChromaSampling, Y4mDecoder, Y4mEncoder, Y4mHeader};
 
 mod types;
 pub use types::ScreenCaptureConfig;
+pub use types::MonitorSelection;
+pub use types::CapturedFrame;
+pub use types::CaptureTarget;
+pub use types::WindowSelector;
+pub use types::WindowMatcher;
+pub use types::WindowInfo;
+pub use types::MonitorInfo;
+pub use types::EncoderSettings;
+pub use types::RateControl;
+pub use types::OddSizePolicy;
+pub use types::RetentionPolicy;
+pub use types::ScreenshotFormat;
+pub use types::ResizeFilter;
+pub use types::AdaptiveFps;
+pub use types::CaptureRegion;
+pub use types::RegionRect;
+pub use types::RegionInfo;
+pub use types::RedactionConfig;
+pub use types::RedactionStyle;
+pub use types::SensitivePattern;
+pub use types::StdoutFormat;
+pub use types::CaptureSchedule;
+pub use types::ScheduleWindow;
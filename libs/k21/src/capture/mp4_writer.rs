@@ -0,0 +1,674 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+
+/// Media timescale of the video track, the usual 90 kHz clock of video streams.
+pub const TIMESCALE: u32 = 90_000;
+const MOVIE_TIMESCALE: u32 = 1000;
+
+const NAL_TYPE_IDR: u8 = 5;
+const NAL_TYPE_SPS: u8 = 7;
+const NAL_TYPE_PPS: u8 = 8;
+
+/// Chunks are written under this extension until they are finalized.
+pub const PART_EXTENSION: &str = "part";
+
+/// User data box holding the wall-clock start of the recording in milliseconds since the Unix epoch.
+pub const START_TIME_BOX: &[u8; 4] = b"k21t";
+
+/// Seconds between the MP4 epoch (1904) and the Unix epoch.
+pub const MP4_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+const SAMPLE_FLAGS_SYNC: u32 = 0x0200_0000;
+const SAMPLE_FLAGS_NON_SYNC: u32 = 0x0101_0000;
+
+/// Writes an H.264 stream into an MP4 file sample by sample.
+///
+/// Samples go straight into the `mdat` box on disk, only the per-sample tables (size, duration,
+/// keyframe flag) are kept in memory until [`Mp4ChunkWriter::finish`] writes the `moov` box.
+///
+/// In fragmented mode the `moov` box is written up front and every `fragment_frames` samples are
+/// flushed as a `moof`/`mdat` pair, so a file cut off by a crash stays playable up to its last
+/// complete fragment. The file carries a `.part` extension until it is finished, and is locked
+/// while it is written so that [`recover_chunk`] of another recorder leaves it alone.
+///
+/// The wall-clock time of the first frame goes into the header, and every sample lasts until the
+/// next one was captured, so the absolute capture time of each frame can be restored when reading.
+pub struct Mp4ChunkWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    part_path: PathBuf,
+    width: u32,
+    height: u32,
+    fragment_frames: Option<u32>,
+    default_duration: u32,
+    start_time: Option<DateTime<Local>>,
+    last_sample_time: Option<DateTime<Local>>,
+    mdat_header_offset: u64,
+    mdat_data_len: u64,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    sample_sizes: Vec<u32>,
+    sample_durations: Vec<u32>,
+    sync_samples: Vec<u32>,
+    sample_buf: Vec<u8>,
+    fragment_buf: Vec<u8>,
+    fragment_count: u32,
+    decode_time: u64,
+    total_samples: usize,
+}
+
+impl Mp4ChunkWriter {
+    /// `default_duration` is used for the last sample, whose duration isn't known from a next capture.
+    pub fn create(
+        path: &Path,
+        width: u32,
+        height: u32,
+        fragment_frames: Option<u32>,
+        default_duration: u32,
+    ) -> Result<Self> {
+        let part_path = part_path(path);
+        let file = File::create(&part_path)?;
+        // Released when the file is closed, also when the recorder gets killed
+        file.lock()?;
+        let mut file = BufWriter::new(file);
+
+        let mut header = Vec::new();
+        write_ftyp(&mut header);
+        let mut mdat_header_offset = 0;
+        if fragment_frames.is_none() {
+            // Placeholder that turns into a 64-bit mdat header if the chunk outgrows 4 GB
+            write_box(&mut header, b"free", |_| {});
+            mdat_header_offset = header.len() as u64;
+            header.extend_from_slice(&0u32.to_be_bytes());
+            header.extend_from_slice(b"mdat");
+        }
+        file.write_all(&header)?;
+
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            part_path,
+            width,
+            height,
+            fragment_frames: fragment_frames.map(|frames| frames.max(1)),
+            default_duration,
+            start_time: None,
+            last_sample_time: None,
+            mdat_header_offset,
+            mdat_data_len: 0,
+            sps: None,
+            pps: None,
+            sample_sizes: Vec::new(),
+            sample_durations: Vec::new(),
+            sync_samples: Vec::new(),
+            sample_buf: Vec::new(),
+            fragment_buf: Vec::new(),
+            fragment_count: 0,
+            decode_time: 0,
+            total_samples: 0,
+        })
+    }
+
+    /// Appends one encoded frame, given as Annex B bitstream, captured at `captured_at`.
+    pub fn write_sample(&mut self, annexb: &[u8], captured_at: DateTime<Local>) -> Result<()> {
+        self.sample_buf.clear();
+        let mut is_sync = false;
+
+        for nal in annexb_nal_units(annexb) {
+            match nal[0] & 0x1F {
+                // Parameter sets belong into the avcC box, not into the samples
+                NAL_TYPE_SPS => {
+                    // The avcC box copies profile, compatibility and level from the SPS
+                    if nal.len() < 4 {
+                        return Err(anyhow::anyhow!("SPS of {} bytes is too short", nal.len()));
+                    }
+                    self.sps.get_or_insert_with(|| nal.to_vec());
+                }
+                NAL_TYPE_PPS => {
+                    self.pps.get_or_insert_with(|| nal.to_vec());
+                }
+                nal_type => {
+                    is_sync |= nal_type == NAL_TYPE_IDR;
+                    self.sample_buf.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+                    self.sample_buf.extend_from_slice(nal);
+                }
+            }
+        }
+
+        if self.sample_buf.is_empty() {
+            return Ok(());
+        }
+
+        // The previous sample lasts until this one was captured
+        if let (Some(last_sample_time), Some(duration)) = (self.last_sample_time, self.sample_durations.last_mut()) {
+            *duration = duration_ticks(captured_at - last_sample_time);
+        }
+        self.start_time.get_or_insert(captured_at);
+        self.last_sample_time = Some(captured_at);
+
+        if let Some(fragment_frames) = self.fragment_frames {
+            if self.total_samples == 0 {
+                self.write_init_segment()?;
+            } else if self.sample_sizes.len() >= fragment_frames as usize {
+                self.write_fragment()?;
+            }
+            self.fragment_buf.extend_from_slice(&self.sample_buf);
+        } else {
+            self.file.write_all(&self.sample_buf)?;
+            self.mdat_data_len += self.sample_buf.len() as u64;
+        }
+
+        self.total_samples += 1;
+        self.sample_sizes.push(self.sample_buf.len() as u32);
+        self.sample_durations.push(self.default_duration);
+        if is_sync {
+            self.sync_samples.push(self.sample_sizes.len() as u32);
+        }
+
+        Ok(())
+    }
+
+    /// Finalizes the file by writing the `moov` box, or the last fragment in fragmented mode, and
+    /// moves it to its final name. An empty chunk is removed instead.
+    pub fn finish(mut self) -> Result<Option<PathBuf>> {
+        if self.total_samples == 0 {
+            drop(self.file);
+            std::fs::remove_file(&self.part_path)?;
+            return Ok(None);
+        }
+
+        if self.fragment_frames.is_some() {
+            self.write_fragment()?;
+        } else {
+            self.write_moov_at_end()?;
+        }
+
+        self.file.flush()?;
+        drop(self.file);
+        std::fs::rename(&self.part_path, &self.path)?;
+
+        Ok(Some(self.path))
+    }
+
+    fn track_info(&self, duration: u64) -> Result<TrackInfo<'_>> {
+        let (Some(sps), Some(pps)) = (&self.sps, &self.pps) else {
+            return Err(anyhow::anyhow!("No SPS/PPS found in {}", self.path.display()));
+        };
+
+        Ok(TrackInfo {
+            width: self.width,
+            height: self.height,
+            sps,
+            pps,
+            duration,
+            start_time: self.start_time,
+        })
+    }
+
+    fn write_moov_at_end(&mut self) -> Result<()> {
+        let mdat_size = 8 + self.mdat_data_len;
+        if mdat_size <= u32::MAX as u64 {
+            self.file.seek(SeekFrom::Start(self.mdat_header_offset))?;
+            self.file.write_all(&(mdat_size as u32).to_be_bytes())?;
+        } else {
+            // Take over the free box in front of mdat for a 64-bit size header
+            self.file.seek(SeekFrom::Start(self.mdat_header_offset - 8))?;
+            self.file.write_all(&1u32.to_be_bytes())?;
+            self.file.write_all(b"mdat")?;
+            self.file.write_all(&(mdat_size + 8).to_be_bytes())?;
+        }
+        self.file.seek(SeekFrom::End(0))?;
+
+        let track = self.track_info(self.sample_durations.iter().map(|d| *d as u64).sum())?;
+        let tables = SampleTables {
+            sizes: &self.sample_sizes,
+            durations: &self.sample_durations,
+            sync_samples: &self.sync_samples,
+            chunk_offset: self.mdat_header_offset + 8,
+        };
+
+        let mut moov = Vec::new();
+        write_moov(&mut moov, &track, &tables, false);
+        self.file.write_all(&moov)?;
+        Ok(())
+    }
+
+    /// Writes the `moov` box of a fragmented file, its sample tables are left empty.
+    fn write_init_segment(&mut self) -> Result<()> {
+        let track = self.track_info(0)?;
+        let tables = SampleTables {
+            sizes: &[],
+            durations: &[],
+            sync_samples: &[],
+            chunk_offset: 0,
+        };
+
+        let mut moov = Vec::new();
+        write_moov(&mut moov, &track, &tables, true);
+        self.file.write_all(&moov)?;
+        Ok(())
+    }
+
+    fn write_fragment(&mut self) -> Result<()> {
+        if self.sample_sizes.is_empty() {
+            return Ok(());
+        }
+        self.fragment_count += 1;
+
+        let mut moof = Vec::new();
+        write_moof(&mut moof, self.fragment_count, self.decode_time, &SampleTables {
+            sizes: &self.sample_sizes,
+            durations: &self.sample_durations,
+            sync_samples: &self.sync_samples,
+            chunk_offset: 0,
+        });
+        self.file.write_all(&moof)?;
+        self.file.write_all(&(8 + self.fragment_buf.len() as u32).to_be_bytes())?;
+        self.file.write_all(b"mdat")?;
+        self.file.write_all(&self.fragment_buf)?;
+        // Get every fragment to disk right away, that's the point of writing fragments
+        self.file.flush()?;
+
+        self.decode_time += self.sample_durations.iter().map(|d| *d as u64).sum::<u64>();
+        self.fragment_buf.clear();
+        self.sample_sizes.clear();
+        self.sample_durations.clear();
+        self.sync_samples.clear();
+        Ok(())
+    }
+}
+
+fn duration_ticks(duration: chrono::Duration) -> u32 {
+    let micros = duration.num_microseconds().unwrap_or(i64::MAX);
+    // Clocks may jump backwards, samples still need a positive duration
+    (micros.saturating_mul(TIMESCALE as i64) / 1_000_000).clamp(1, u32::MAX as i64) as u32
+}
+
+/// Path a chunk is written to until it is finished, e.g. `output-0.mp4.part`.
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".");
+    part.push(PART_EXTENSION);
+    PathBuf::from(part)
+}
+
+/// Repairs a chunk left behind as `.part` file, e.g. after the recorder was killed.
+///
+/// Fragmented files are cut after their last complete fragment and moved to their final name.
+/// Files without fragments can't be repaired and are left alone, as are files that another
+/// recorder is still writing.
+pub fn recover_chunk(part_path: &Path) -> Result<Option<PathBuf>> {
+    let mut file = OpenOptions::new().read(true).write(true).open(part_path)?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(std::fs::TryLockError::WouldBlock) => {
+            log::debug!("{} is being written, not recovering it", part_path.display());
+            return Ok(None);
+        }
+        Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+    }
+    let file_len = file.metadata()?.len();
+
+    let mut offset = 0;
+    let mut has_moov = false;
+    let mut moof_pending = false;
+    let mut valid_end = None;
+
+    while offset + 8 <= file_len {
+        let mut header = [0u8; 8];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        let mut size = u32::from_be_bytes(header[..4].try_into()?) as u64;
+        if size == 1 {
+            let mut large_size = [0u8; 8];
+            file.read_exact(&mut large_size)?;
+            size = u64::from_be_bytes(large_size);
+        }
+        // A size of zero runs until the end of the file, i.e. the mdat of an unfinished plain chunk
+        if size < 8 || offset + size > file_len {
+            break;
+        }
+
+        match &header[4..] {
+            b"moov" => has_moov = true,
+            b"moof" => moof_pending = true,
+            b"mdat" if moof_pending && has_moov => {
+                moof_pending = false;
+                valid_end = Some(offset + size);
+            }
+            _ => {}
+        }
+        offset += size;
+    }
+
+    let Some(valid_end) = valid_end else {
+        log::warn!("No complete fragment in {}, can't recover it", part_path.display());
+        return Ok(None);
+    };
+
+    file.set_len(valid_end)?;
+    drop(file);
+
+    let path = part_path.with_extension("");
+    std::fs::rename(part_path, &path)?;
+    log::info!("Recovered {} bytes of {} into {}", valid_end, part_path.display(), path.display());
+
+    Ok(Some(path))
+}
+
+/// Splits an Annex B bitstream into NAL units, without start codes.
+pub(crate) fn annexb_nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut units = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+
+    while i + 2 < data.len() {
+        if data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 1 {
+            if let Some(start) = start {
+                units.push(trim_trailing_zeros(&data[start..i]));
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(start) = start {
+        units.push(&data[start.min(data.len())..]);
+    }
+
+    units.into_iter().filter(|unit| !unit.is_empty()).collect()
+}
+
+fn trim_trailing_zeros(nal: &[u8]) -> &[u8] {
+    let end = nal.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    &nal[..end]
+}
+
+struct TrackInfo<'a> {
+    width: u32,
+    height: u32,
+    sps: &'a [u8],
+    pps: &'a [u8],
+    duration: u64,
+    start_time: Option<DateTime<Local>>,
+}
+
+struct SampleTables<'a> {
+    sizes: &'a [u32],
+    durations: &'a [u32],
+    sync_samples: &'a [u32],
+    chunk_offset: u64,
+}
+
+fn write_box(out: &mut Vec<u8>, name: &[u8; 4], content: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(name);
+    content(out);
+    let size = (out.len() - start) as u32;
+    out[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+fn write_full_box(out: &mut Vec<u8>, name: &[u8; 4], version: u8, flags: u32, content: impl FnOnce(&mut Vec<u8>)) {
+    write_box(out, name, |out| {
+        out.extend_from_slice(&((version as u32) << 24 | flags).to_be_bytes());
+        content(out);
+    })
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_matrix(out: &mut Vec<u8>) {
+    for value in [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000u32] {
+        put_u32(out, value);
+    }
+}
+
+fn write_ftyp(out: &mut Vec<u8>) {
+    write_box(out, b"ftyp", |out| {
+        out.extend_from_slice(b"isom");
+        put_u32(out, 512);
+        for brand in [b"isom", b"iso2", b"avc1", b"mp41"] {
+            out.extend_from_slice(brand);
+        }
+    });
+}
+
+fn write_moov(out: &mut Vec<u8>, track: &TrackInfo, tables: &SampleTables, fragmented: bool) {
+    let movie_duration = track.duration * MOVIE_TIMESCALE as u64 / TIMESCALE as u64;
+    let creation_time = track
+        .start_time
+        .map_or(0, |start_time| (start_time.timestamp() + MP4_EPOCH_OFFSET) as u64);
+
+    write_box(out, b"moov", |out| {
+        write_full_box(out, b"mvhd", 1, 0, |out| {
+            put_u64(out, creation_time);
+            put_u64(out, creation_time); // modification time
+            put_u32(out, MOVIE_TIMESCALE);
+            put_u64(out, movie_duration);
+            put_u32(out, 0x0001_0000); // rate 1.0
+            put_u16(out, 0x0100); // volume 1.0
+            out.extend_from_slice(&[0; 10]);
+            put_matrix(out);
+            out.extend_from_slice(&[0; 24]);
+            put_u32(out, 2); // next track id
+        });
+
+        write_box(out, b"trak", |out| {
+            write_full_box(out, b"tkhd", 1, 0x3, |out| {
+                put_u64(out, creation_time);
+                put_u64(out, creation_time);
+                put_u32(out, 1); // track id
+                put_u32(out, 0);
+                put_u64(out, movie_duration);
+                out.extend_from_slice(&[0; 8]);
+                put_u16(out, 0); // layer
+                put_u16(out, 0); // alternate group
+                put_u16(out, 0); // volume
+                put_u16(out, 0);
+                put_matrix(out);
+                put_u32(out, track.width << 16);
+                put_u32(out, track.height << 16);
+            });
+
+            write_box(out, b"mdia", |out| {
+                write_full_box(out, b"mdhd", 1, 0, |out| {
+                    put_u64(out, creation_time);
+                    put_u64(out, creation_time);
+                    put_u32(out, TIMESCALE);
+                    put_u64(out, track.duration);
+                    put_u16(out, 0x55C4); // "und"
+                    put_u16(out, 0);
+                });
+
+                write_full_box(out, b"hdlr", 0, 0, |out| {
+                    put_u32(out, 0);
+                    out.extend_from_slice(b"vide");
+                    out.extend_from_slice(&[0; 12]);
+                    out.extend_from_slice(b"VideoHandler\0");
+                });
+
+                write_box(out, b"minf", |out| {
+                    write_full_box(out, b"vmhd", 0, 1, |out| {
+                        out.extend_from_slice(&[0; 8]);
+                    });
+
+                    write_box(out, b"dinf", |out| {
+                        write_full_box(out, b"dref", 0, 0, |out| {
+                            put_u32(out, 1);
+                            write_full_box(out, b"url ", 0, 1, |_| {});
+                        });
+                    });
+
+                    write_box(out, b"stbl", |out| {
+                        write_stsd(out, track);
+                        write_sample_tables(out, tables);
+                    });
+                });
+            });
+        });
+
+        // mvhd only has second precision, the start down to the millisecond goes into user data
+        if let Some(start_time) = track.start_time {
+            write_box(out, b"udta", |out| {
+                write_full_box(out, START_TIME_BOX, 0, 0, |out| {
+                    put_u64(out, start_time.timestamp_millis() as u64);
+                });
+            });
+        }
+
+        if fragmented {
+            write_box(out, b"mvex", |out| {
+                write_full_box(out, b"trex", 0, 0, |out| {
+                    put_u32(out, 1); // track id
+                    put_u32(out, 1); // sample description index
+                    put_u32(out, 0); // default duration
+                    put_u32(out, 0); // default size
+                    put_u32(out, 0); // default flags
+                });
+            });
+        }
+    });
+}
+
+fn write_stsd(out: &mut Vec<u8>, track: &TrackInfo) {
+    write_full_box(out, b"stsd", 0, 0, |out| {
+        put_u32(out, 1);
+        write_box(out, b"avc1", |out| {
+            out.extend_from_slice(&[0; 6]);
+            put_u16(out, 1); // data reference index
+            out.extend_from_slice(&[0; 16]);
+            put_u16(out, track.width as u16);
+            put_u16(out, track.height as u16);
+            put_u32(out, 0x0048_0000); // 72 dpi
+            put_u32(out, 0x0048_0000);
+            put_u32(out, 0);
+            put_u16(out, 1); // frame count
+            out.extend_from_slice(&[0; 32]); // compressor name
+            put_u16(out, 0x0018); // depth
+            put_u16(out, 0xFFFF);
+
+            write_box(out, b"avcC", |out| {
+                out.push(1); // configuration version
+                out.extend_from_slice(&track.sps[1..4]); // profile, compatibility, level
+                out.push(0xFF); // 4 byte NAL lengths
+                out.push(0xE1); // one SPS
+                put_u16(out, track.sps.len() as u16);
+                out.extend_from_slice(track.sps);
+                out.push(1); // one PPS
+                put_u16(out, track.pps.len() as u16);
+                out.extend_from_slice(track.pps);
+            });
+        });
+    });
+}
+
+fn write_sample_tables(out: &mut Vec<u8>, tables: &SampleTables) {
+    // Durations are run-length encoded as (sample count, duration) pairs
+    let mut time_to_sample: Vec<(u32, u32)> = Vec::new();
+    for duration in tables.durations {
+        match time_to_sample.last_mut() {
+            Some((count, last)) if last == duration => *count += 1,
+            _ => time_to_sample.push((1, *duration)),
+        }
+    }
+
+    write_full_box(out, b"stts", 0, 0, |out| {
+        put_u32(out, time_to_sample.len() as u32);
+        for (count, duration) in &time_to_sample {
+            put_u32(out, *count);
+            put_u32(out, *duration);
+        }
+    });
+
+    // The tables of a fragmented file stay empty, its samples are described by the fragments
+    let chunk_count = if tables.sizes.is_empty() { 0 } else { 1 };
+
+    if chunk_count > 0 {
+        write_full_box(out, b"stss", 0, 0, |out| {
+            put_u32(out, tables.sync_samples.len() as u32);
+            for sample in tables.sync_samples {
+                put_u32(out, *sample);
+            }
+        });
+    }
+
+    // All samples live in one chunk right at the start of mdat
+    write_full_box(out, b"stsc", 0, 0, |out| {
+        put_u32(out, chunk_count);
+        if chunk_count > 0 {
+            put_u32(out, 1);
+            put_u32(out, tables.sizes.len() as u32);
+            put_u32(out, 1);
+        }
+    });
+
+    write_full_box(out, b"stsz", 0, 0, |out| {
+        put_u32(out, 0);
+        put_u32(out, tables.sizes.len() as u32);
+        for size in tables.sizes {
+            put_u32(out, *size);
+        }
+    });
+
+    write_full_box(out, b"stco", 0, 0, |out| {
+        put_u32(out, chunk_count);
+        if chunk_count > 0 {
+            put_u32(out, tables.chunk_offset as u32);
+        }
+    });
+}
+
+fn write_moof(out: &mut Vec<u8>, sequence_number: u32, decode_time: u64, tables: &SampleTables) {
+    let moof_start = out.len();
+    let mut data_offset_pos = 0;
+
+    write_box(out, b"moof", |out| {
+        write_full_box(out, b"mfhd", 0, 0, |out| {
+            put_u32(out, sequence_number);
+        });
+
+        write_box(out, b"traf", |out| {
+            // Sample data offsets are relative to the start of the moof box
+            write_full_box(out, b"tfhd", 0, 0x02_0000, |out| {
+                put_u32(out, 1); // track id
+            });
+
+            write_full_box(out, b"tfdt", 1, 0, |out| {
+                put_u64(out, decode_time);
+            });
+
+            // Data offset, per-sample duration, size and flags present
+            write_full_box(out, b"trun", 0, 0x0701, |out| {
+                put_u32(out, tables.sizes.len() as u32);
+                data_offset_pos = out.len();
+                put_u32(out, 0);
+                for (i, (size, duration)) in tables.sizes.iter().zip(tables.durations).enumerate() {
+                    let is_sync = tables.sync_samples.contains(&(i as u32 + 1));
+                    put_u32(out, *duration);
+                    put_u32(out, *size);
+                    put_u32(out, if is_sync { SAMPLE_FLAGS_SYNC } else { SAMPLE_FLAGS_NON_SYNC });
+                }
+            });
+        });
+    });
+
+    // Samples start right behind the header of the mdat box following the moof
+    let data_offset = (out.len() - moof_start + 8) as u32;
+    out[data_offset_pos..data_offset_pos + 4].copy_from_slice(&data_offset.to_be_bytes());
+}
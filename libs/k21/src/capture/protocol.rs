@@ -0,0 +1,534 @@
+//! Frame stream protocol of `k21-screen --stdout` and `k21-processor --stdin`.
+//!
+//! All integers are little-endian. A stream starts with a stream header:
+//!
+//! | bytes | field                                   |
+//! |-------|-----------------------------------------|
+//! | 4     | magic `K21S`                            |
+//! | 2     | protocol version, currently 1           |
+//! | 2     | reserved, 0                             |
+//!
+//! followed by any number of frames:
+//!
+//! | bytes | field                                                      |
+//! |-------|------------------------------------------------------------|
+//! | 4     | magic `K21F`                                               |
+//! | 2     | header length, bytes that follow up to the payload (40)    |
+//! | 8     | frame number                                               |
+//! | 8     | capture time, microseconds since the unix epoch (signed)   |
+//! | 4     | monitor id, `0xFFFFFFFF` if unknown                        |
+//! | 4     | width                                                      |
+//! | 4     | height                                                     |
+//! | 1     | pixel format: 1 = RGB8, 2 = RGBA8                          |
+//! | 1     | payload encoding: 0 = raw, 1 = PNG, 2 = JPEG, 3 = zstd     |
+//! | 2     | reserved, 0                                                |
+//! | 4     | payload length                                             |
+//! | 4     | CRC-32 of the header from the header length on             |
+//! | n     | payload                                                    |
+//!
+//! Raw and zstd payloads hold the pixels row by row in the pixel format, PNG and JPEG
+//! payloads decode to an image of the given size. Later versions keep the stream header and
+//! only append frame header fields before the CRC, so decoders read streams of any later
+//! version and skip the fields they don't know. A decoder that hits a corrupt header skips
+//! ahead to the next frame magic.
+
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Local};
+use image::{DynamicImage, ImageFormat, RgbImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::{CapturedFrame, SharedFrame};
+
+pub const PROTOCOL_VERSION: u16 = 1;
+pub const STREAM_MAGIC: [u8; 4] = *b"K21S";
+pub const FRAME_MAGIC: [u8; 4] = *b"K21F";
+
+const FRAME_HEADER_LEN: u16 = 40;
+const NO_MONITOR: u32 = u32::MAX;
+const MAX_PAYLOAD_LEN: u32 = 1 << 30;
+const ZSTD_LEVEL: i32 = 3;
+const JPEG_QUALITY_DEFAULT: u8 = 80;
+
+/// How the pixels of a frame are stored in the stream.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum FrameEncoding {
+    /// Uncompressed pixels, cheapest to produce
+    #[default]
+    Raw,
+    Png,
+    /// Lossy, the smallest frames but OCR may suffer at low quality
+    Jpeg,
+    /// Lossless like PNG, compresses faster
+    Zstd,
+}
+
+impl std::str::FromStr for FrameEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "raw" => Ok(FrameEncoding::Raw),
+            "png" => Ok(FrameEncoding::Png),
+            "jpeg" | "jpg" => Ok(FrameEncoding::Jpeg),
+            "zstd" | "zst" => Ok(FrameEncoding::Zstd),
+            _ => bail!("Unknown frame encoding {:?}", s),
+        }
+    }
+}
+
+impl FrameEncoding {
+    fn to_byte(self) -> u8 {
+        match self {
+            FrameEncoding::Raw => 0,
+            FrameEncoding::Png => 1,
+            FrameEncoding::Jpeg => 2,
+            FrameEncoding::Zstd => 3,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(FrameEncoding::Raw),
+            1 => Some(FrameEncoding::Png),
+            2 => Some(FrameEncoding::Jpeg),
+            3 => Some(FrameEncoding::Zstd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum PixelFormat {
+    Rgb8,
+    Rgba8,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Rgba8 => 4,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            PixelFormat::Rgb8 => 1,
+            PixelFormat::Rgba8 => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(PixelFormat::Rgb8),
+            2 => Some(PixelFormat::Rgba8),
+            _ => None,
+        }
+    }
+}
+
+/// Metadata of a frame in the stream, everything but the payload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameHeader {
+    pub frame_number: u64,
+    pub captured_at: DateTime<Local>,
+    pub monitor_id: Option<u32>,
+    pub width: u32,
+    pub height: u32,
+    pub pixel_format: PixelFormat,
+    pub encoding: FrameEncoding,
+    pub payload_len: u32,
+}
+
+impl FrameHeader {
+    /// The header as written after the frame magic, including the header length and CRC.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 + FRAME_HEADER_LEN as usize);
+        bytes.extend_from_slice(&FRAME_HEADER_LEN.to_le_bytes());
+        bytes.extend_from_slice(&self.frame_number.to_le_bytes());
+        bytes.extend_from_slice(&self.captured_at.timestamp_micros().to_le_bytes());
+        bytes.extend_from_slice(&self.monitor_id.unwrap_or(NO_MONITOR).to_le_bytes());
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.push(self.pixel_format.to_byte());
+        bytes.push(self.encoding.to_byte());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&self.payload_len.to_le_bytes());
+        let crc = crc32fast::hash(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// Parses the header length field and the `header` bytes that follow it.
+    fn parse(header_len: [u8; 2], header: &[u8]) -> Result<Self> {
+        let (fields, crc) = header.split_at(header.len() - 4);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header_len);
+        hasher.update(fields);
+        if hasher.finalize().to_le_bytes() != crc {
+            bail!("Frame header checksum mismatch");
+        }
+
+        let u32_at = |at: usize| u32::from_le_bytes(fields[at..at + 4].try_into().unwrap());
+        let frame_number = u64::from_le_bytes(fields[0..8].try_into().unwrap());
+        let captured_at_micros = i64::from_le_bytes(fields[8..16].try_into().unwrap());
+        let captured_at = DateTime::from_timestamp_micros(captured_at_micros)
+            .ok_or_else(|| anyhow!("Invalid capture time {}", captured_at_micros))?
+            .with_timezone(&Local);
+        let monitor_id = Some(u32_at(16)).filter(|id| *id != NO_MONITOR);
+        let pixel_format = PixelFormat::from_byte(fields[28])
+            .ok_or_else(|| anyhow!("Unknown pixel format {}", fields[28]))?;
+        let encoding = FrameEncoding::from_byte(fields[29])
+            .ok_or_else(|| anyhow!("Unknown payload encoding {}", fields[29]))?;
+        let payload_len = u32_at(32);
+        if payload_len > MAX_PAYLOAD_LEN {
+            bail!("Frame payload of {} bytes is too large", payload_len);
+        }
+
+        Ok(Self {
+            frame_number,
+            captured_at,
+            monitor_id,
+            width: u32_at(20),
+            height: u32_at(24),
+            pixel_format,
+            encoding,
+            payload_len,
+        })
+    }
+
+    fn raw_len(&self) -> usize {
+        self.width as usize * self.height as usize * self.pixel_format.bytes_per_pixel()
+    }
+}
+
+/// Header that starts every frame stream.
+pub fn stream_header() -> [u8; 8] {
+    let mut header = [0u8; 8];
+    header[..4].copy_from_slice(&STREAM_MAGIC);
+    header[4..6].copy_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+    header
+}
+
+/// Encodes `frame` with its magic, header and payload, ready to be appended to a stream.
+pub fn encode_frame(frame: &CapturedFrame, encoding: FrameEncoding, compression_quality: u8) -> Result<Vec<u8>> {
+    let rgb = frame.image.to_rgb8();
+    let payload = match encoding {
+        FrameEncoding::Raw => rgb.into_raw(),
+        FrameEncoding::Png => {
+            let mut bytes = Vec::new();
+            rgb.write_to(&mut std::io::Cursor::new(&mut bytes), ImageFormat::Png)?;
+            bytes
+        }
+        FrameEncoding::Jpeg => {
+            let mut bytes = Vec::new();
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, compression_quality.clamp(1, 100));
+            DynamicImage::ImageRgb8(rgb).write_with_encoder(encoder)?;
+            bytes
+        }
+        FrameEncoding::Zstd => zstd::encode_all(rgb.as_raw().as_slice(), ZSTD_LEVEL)?,
+    };
+
+    let payload_len = u32::try_from(payload.len())
+        .ok()
+        .filter(|len| *len <= MAX_PAYLOAD_LEN)
+        .ok_or_else(|| anyhow!("Frame {} is too large for the stream", frame.frame_number))?;
+    let header = FrameHeader {
+        frame_number: frame.frame_number,
+        captured_at: frame.captured_at,
+        monitor_id: frame.monitor_id,
+        width: frame.image.width(),
+        height: frame.image.height(),
+        pixel_format: PixelFormat::Rgb8,
+        encoding,
+        payload_len,
+    };
+
+    let header = header.to_bytes();
+    let mut bytes = Vec::with_capacity(FRAME_MAGIC.len() + header.len() + payload.len());
+    bytes.extend_from_slice(&FRAME_MAGIC);
+    bytes.extend_from_slice(&header);
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+/// Decodes the payload of a frame described by `header`.
+pub fn decode_payload(header: &FrameHeader, payload: Vec<u8>) -> Result<DynamicImage> {
+    let pixels = match header.encoding {
+        FrameEncoding::Raw => payload,
+        FrameEncoding::Zstd => zstd::decode_all(payload.as_slice())?,
+        FrameEncoding::Png | FrameEncoding::Jpeg => {
+            let format = match header.encoding {
+                FrameEncoding::Png => ImageFormat::Png,
+                _ => ImageFormat::Jpeg,
+            };
+            let image = image::load_from_memory_with_format(&payload, format)?;
+            if (image.width(), image.height()) != (header.width, header.height) {
+                bail!(
+                    "Frame {} is {}x{} but its header says {}x{}",
+                    header.frame_number,
+                    image.width(),
+                    image.height(),
+                    header.width,
+                    header.height
+                );
+            }
+            return Ok(image);
+        }
+    };
+
+    if pixels.len() != header.raw_len() {
+        bail!(
+            "Frame {} has {} bytes of pixels, expected {}",
+            header.frame_number,
+            pixels.len(),
+            header.raw_len()
+        );
+    }
+    let image = match header.pixel_format {
+        PixelFormat::Rgb8 => RgbImage::from_raw(header.width, header.height, pixels).map(DynamicImage::ImageRgb8),
+        PixelFormat::Rgba8 => RgbaImage::from_raw(header.width, header.height, pixels).map(DynamicImage::ImageRgba8),
+    };
+    image.ok_or_else(|| anyhow!("Invalid pixels in frame {}", header.frame_number))
+}
+
+/// Writes captured frames as a frame stream, the stream header goes out with the first frame.
+pub struct FrameEncoder<W> {
+    writer: W,
+    encoding: FrameEncoding,
+    compression_quality: u8,
+    header_written: bool,
+}
+
+impl<W: AsyncWrite + Unpin> FrameEncoder<W> {
+    pub fn new(writer: W, encoding: FrameEncoding) -> Self {
+        Self {
+            writer,
+            encoding,
+            compression_quality: JPEG_QUALITY_DEFAULT,
+            header_written: false,
+        }
+    }
+
+    /// Quality (1-100) of JPEG payloads.
+    pub fn with_compression_quality(mut self, compression_quality: u8) -> Self {
+        self.compression_quality = compression_quality;
+        self
+    }
+
+    pub async fn write_frame(&mut self, frame: &CapturedFrame) -> Result<()> {
+        let bytes = encode_frame(frame, self.encoding, self.compression_quality)?;
+        self.write_encoded(&bytes).await
+    }
+
+    /// Like [`FrameEncoder::write_frame`], compressing the frame on the blocking thread pool.
+    pub async fn write_shared_frame(&mut self, frame: SharedFrame) -> Result<()> {
+        let (encoding, compression_quality) = (self.encoding, self.compression_quality);
+        let bytes = tokio::task::spawn_blocking(move || encode_frame(&frame, encoding, compression_quality)).await??;
+        self.write_encoded(&bytes).await
+    }
+
+    async fn write_encoded(&mut self, bytes: &[u8]) -> Result<()> {
+        if !self.header_written {
+            self.writer.write_all(&stream_header()).await?;
+            self.header_written = true;
+        }
+        self.writer.write_all(bytes).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads captured frames from a frame stream.
+pub struct FrameDecoder<R> {
+    reader: R,
+    /// Bytes read ahead that go back into the stream while resynchronizing
+    pending: VecDeque<u8>,
+    header_read: bool,
+    skipped_bytes: u64,
+}
+
+impl<R: AsyncRead + Unpin> FrameDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            pending: VecDeque::new(),
+            header_read: false,
+            skipped_bytes: 0,
+        }
+    }
+
+    /// Bytes skipped so far to find the next frame after corrupt data.
+    pub fn skipped_bytes(&self) -> u64 {
+        self.skipped_bytes
+    }
+
+    /// Next frame, `None` at the end of the stream.
+    ///
+    /// Fails if the stream doesn't start with a stream header, corrupt frames are skipped.
+    pub async fn read_frame(&mut self) -> Result<Option<CapturedFrame>> {
+        if !self.header_read {
+            let mut magic = [0u8; 4];
+            if !self.read_or_eof(&mut magic).await? {
+                return Ok(None);
+            }
+            if magic != STREAM_MAGIC {
+                bail!("Not a k21 frame stream, it doesn't start with {:?}", STREAM_MAGIC);
+            }
+            self.read_stream_header().await?;
+        }
+
+        loop {
+            let Some(magic) = self.next_magic().await? else {
+                return Ok(None);
+            };
+            if magic == STREAM_MAGIC {
+                // Another stream was appended to this one
+                self.read_stream_header().await?;
+                continue;
+            }
+
+            let mut header_len = [0u8; 2];
+            if !self.read_or_eof(&mut header_len).await? {
+                return Ok(None);
+            }
+            let len = u16::from_le_bytes(header_len);
+            let mut header = vec![0u8; len as usize];
+            if len >= FRAME_HEADER_LEN && !self.read_or_eof(&mut header).await? {
+                return Ok(None);
+            }
+
+            let parsed = if len >= FRAME_HEADER_LEN {
+                FrameHeader::parse(header_len, &header)
+            } else {
+                Err(anyhow!("Frame header of {} bytes is too short", len))
+            };
+            let header = match parsed {
+                Ok(header) => header,
+                Err(e) => {
+                    log::warn!("Skipping corrupt frame: {}", e);
+                    // The real next frame may start within what was read as this header
+                    let mut unread = magic[1..].to_vec();
+                    unread.extend_from_slice(&header_len);
+                    if len >= FRAME_HEADER_LEN {
+                        unread.extend_from_slice(&header);
+                    }
+                    self.skipped_bytes += 1;
+                    for byte in unread.into_iter().rev() {
+                        self.pending.push_front(byte);
+                    }
+                    continue;
+                }
+            };
+
+            let mut payload = vec![0u8; header.payload_len as usize];
+            if !self.read_or_eof(&mut payload).await? {
+                log::warn!("Stream ended within frame {}", header.frame_number);
+                return Ok(None);
+            }
+
+            match decode_payload(&header, payload) {
+                Ok(image) => {
+                    let mut frame = CapturedFrame::new(header.frame_number, image).with_captured_at(header.captured_at);
+                    frame.monitor_id = header.monitor_id;
+                    return Ok(Some(frame));
+                }
+                Err(e) => log::warn!("Skipping frame {}: {}", header.frame_number, e),
+            }
+        }
+    }
+
+    async fn read_stream_header(&mut self) -> Result<()> {
+        let mut header = [0u8; 4];
+        if !self.read_or_eof(&mut header).await? {
+            bail!("Stream ended within its header");
+        }
+        let version = u16::from_le_bytes([header[0], header[1]]);
+        if version == 0 {
+            bail!("Unsupported frame stream version {}", version);
+        }
+        if version > PROTOCOL_VERSION {
+            log::debug!("Reading frame stream version {} as version {}", version, PROTOCOL_VERSION);
+        }
+        self.header_read = true;
+        Ok(())
+    }
+
+    /// Reads up to the next frame or stream magic, skipping anything else.
+    async fn next_magic(&mut self) -> Result<Option<[u8; 4]>> {
+        let mut magic = [0u8; 4];
+        if !self.read_or_eof(&mut magic).await? {
+            return Ok(None);
+        }
+
+        let mut skipped = 0u64;
+        while magic != FRAME_MAGIC && magic != STREAM_MAGIC {
+            let mut byte = [0u8; 1];
+            if !self.read_or_eof(&mut byte).await? {
+                self.skipped_bytes += skipped + 4;
+                return Ok(None);
+            }
+            magic.rotate_left(1);
+            magic[3] = byte[0];
+            skipped += 1;
+        }
+
+        if skipped > 0 {
+            log::warn!("Skipped {} bytes to the next frame", skipped);
+            self.skipped_bytes += skipped;
+        }
+        Ok(Some(magic))
+    }
+
+    /// Fills `buf`, `false` if the stream ends first.
+    async fn read_or_eof(&mut self, buf: &mut [u8]) -> Result<bool> {
+        let from_pending = buf.len().min(self.pending.len());
+        for (byte, pending) in buf.iter_mut().zip(self.pending.drain(..from_pending)) {
+            *byte = pending;
+        }
+
+        match self.reader.read_exact(&mut buf[from_pending..]).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reads_later_versions() {
+        let frame = CapturedFrame::new(3, DynamicImage::new_rgb8(2, 2));
+        let mut stream = stream_header().to_vec();
+        stream[4..6].copy_from_slice(&(PROTOCOL_VERSION + 1).to_le_bytes());
+
+        // A frame header with a field appended before the CRC
+        let encoded = encode_frame(&frame, FrameEncoding::Raw, JPEG_QUALITY_DEFAULT).unwrap();
+        let header_end = FRAME_MAGIC.len() + 2 + FRAME_HEADER_LEN as usize - 4;
+        let mut header = (FRAME_HEADER_LEN + 4).to_le_bytes().to_vec();
+        header.extend_from_slice(&encoded[FRAME_MAGIC.len() + 2..header_end]);
+        header.extend_from_slice(&[1, 2, 3, 4]);
+        let crc = crc32fast::hash(&header);
+        stream.extend_from_slice(&FRAME_MAGIC);
+        stream.extend_from_slice(&header);
+        stream.extend_from_slice(&crc.to_le_bytes());
+        stream.extend_from_slice(&encoded[header_end + 4..]);
+
+        let mut decoder = FrameDecoder::new(stream.as_slice());
+        let decoded = decoder.read_frame().await.unwrap().expect("Stream should have a frame");
+        assert_eq!(decoded.frame_number, 3);
+        assert_eq!(decoder.skipped_bytes(), 0);
+        assert!(decoder.read_frame().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,126 @@
+use std::io::ErrorKind;
+
+use anyhow::{bail, Result};
+use image::{DynamicImage, GrayImage, RgbImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::y4m::{yuv_to_rgb, ChromaSampling};
+use super::CapturedFrame;
+
+/// Pixel layouts of headerless frames, named like ffmpeg's `-pix_fmt`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum RawPixelFormat {
+    #[default]
+    Rgb24,
+    Bgr24,
+    Rgba,
+    Bgra,
+    Gray,
+    /// Planar limited range 4:2:0
+    Yuv420p,
+    /// Planar limited range 4:4:4
+    Yuv444p,
+}
+
+impl std::str::FromStr for RawPixelFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "rgb24" => Ok(RawPixelFormat::Rgb24),
+            "bgr24" => Ok(RawPixelFormat::Bgr24),
+            "rgba" => Ok(RawPixelFormat::Rgba),
+            "bgra" => Ok(RawPixelFormat::Bgra),
+            "gray" | "grey" | "gray8" => Ok(RawPixelFormat::Gray),
+            "yuv420p" | "i420" => Ok(RawPixelFormat::Yuv420p),
+            "yuv444p" => Ok(RawPixelFormat::Yuv444p),
+            _ => bail!("Unknown raw pixel format {:?}", s),
+        }
+    }
+}
+
+impl RawPixelFormat {
+    /// Bytes of a frame of this format.
+    pub fn frame_len(self, width: u32, height: u32) -> usize {
+        let pixels = width as usize * height as usize;
+        match self {
+            RawPixelFormat::Rgb24 | RawPixelFormat::Bgr24 => pixels * 3,
+            RawPixelFormat::Rgba | RawPixelFormat::Bgra => pixels * 4,
+            RawPixelFormat::Gray => pixels,
+            RawPixelFormat::Yuv420p => ChromaSampling::C420.frame_len(width, height),
+            RawPixelFormat::Yuv444p => ChromaSampling::C444.frame_len(width, height),
+        }
+    }
+
+    fn to_image(self, mut data: Vec<u8>, width: u32, height: u32) -> Result<DynamicImage> {
+        let image = match self {
+            RawPixelFormat::Rgb24 => RgbImage::from_raw(width, height, data).map(DynamicImage::ImageRgb8),
+            RawPixelFormat::Bgr24 => {
+                data.chunks_exact_mut(3).for_each(|pixel| pixel.swap(0, 2));
+                RgbImage::from_raw(width, height, data).map(DynamicImage::ImageRgb8)
+            }
+            RawPixelFormat::Rgba => RgbaImage::from_raw(width, height, data).map(DynamicImage::ImageRgba8),
+            RawPixelFormat::Bgra => {
+                data.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+                RgbaImage::from_raw(width, height, data).map(DynamicImage::ImageRgba8)
+            }
+            RawPixelFormat::Gray => GrayImage::from_raw(width, height, data).map(DynamicImage::ImageLuma8),
+            RawPixelFormat::Yuv420p => {
+                Some(DynamicImage::ImageRgb8(yuv_to_rgb(&data, width, height, ChromaSampling::C420, false)?))
+            }
+            RawPixelFormat::Yuv444p => {
+                Some(DynamicImage::ImageRgb8(yuv_to_rgb(&data, width, height, ChromaSampling::C444, false)?))
+            }
+        };
+        image.ok_or_else(|| anyhow::anyhow!("Invalid {:?} frame of {}x{}", self, width, height))
+    }
+}
+
+/// Reads headerless frames of a known size and pixel format, e.g. from `ffmpeg -f rawvideo -pix_fmt rgb24 -`.
+pub struct RawVideoDecoder<R> {
+    reader: R,
+    width: u32,
+    height: u32,
+    pixel_format: RawPixelFormat,
+    frames_read: u64,
+}
+
+impl<R: AsyncRead + Unpin> RawVideoDecoder<R> {
+    pub fn new(reader: R, width: u32, height: u32, pixel_format: RawPixelFormat) -> Result<Self> {
+        if width == 0 || height == 0 {
+            bail!("Raw video needs a frame size, got {}x{}", width, height);
+        }
+        Ok(Self {
+            reader,
+            width,
+            height,
+            pixel_format,
+            frames_read: 0,
+        })
+    }
+
+    /// Next frame numbered from 1 and timed when it was read, `None` at the end of the stream.
+    pub async fn read_frame(&mut self) -> Result<Option<CapturedFrame>> {
+        let mut data = vec![0u8; self.pixel_format.frame_len(self.width, self.height)];
+        let mut read = 0;
+        while read < data.len() {
+            match self.reader.read(&mut data[read..]).await {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if read < data.len() {
+            if read > 0 {
+                log::warn!("Raw video ended within frame {}, {} of {} bytes", self.frames_read + 1, read, data.len());
+            }
+            return Ok(None);
+        }
+
+        let image = self.pixel_format.to_image(data, self.width, self.height)?;
+        self.frames_read += 1;
+        Ok(Some(CapturedFrame::new(self.frames_read, image)))
+    }
+}
@@ -0,0 +1,197 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Result;
+
+use super::{ChunkManifest, RetentionPolicy};
+use crate::upload::Mp4FrameDecoder;
+
+/// Sub directory of an output directory that downsampled videos are written to.
+pub const DOWNSAMPLED_DIR: &str = "downsampled";
+
+const RECORDING_EXTENSIONS: [&str; 5] = ["mp4", "png", "jpg", "jpeg", "webp"];
+
+/// What a run of [`enforce_retention`] cleaned up.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionReport {
+    pub deleted_files: usize,
+    pub deleted_bytes: u64,
+    pub downsampled_videos: usize,
+    /// Expired videos kept because downsampling them failed, the next run retries them
+    pub failed_downsamples: usize,
+}
+
+/// A screenshot or video chunk, together with the manifest of a chunk.
+struct Recording {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+    // Frames downsampled from an expired video, they outlive the maximum age
+    downsampled: bool,
+}
+
+impl Recording {
+    fn is_video(&self) -> bool {
+        self.path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mp4"))
+    }
+}
+
+/// Applies `policy` to the recordings in `dir` and its downsampled frames, oldest first.
+///
+/// Recordings older than the maximum age are removed, or downsampled to one PNG per minute first.
+/// Downsampled frames are kept regardless of their age, they are what remains of expired videos.
+/// Videos that fail to downsample are kept until a later run succeeds.
+/// Then the oldest recordings, downsampled frames included, are removed until the directory is
+/// within its size and file limits.
+/// Chunks still being written (`.part` files) are never touched.
+pub fn enforce_retention(dir: &Path, policy: &RetentionPolicy) -> Result<RetentionReport> {
+    let mut report = RetentionReport::default();
+    let downsampled_dir = dir.join(DOWNSAMPLED_DIR);
+
+    let mut recordings = list_recordings(dir, false)?;
+    recordings.extend(list_recordings(&downsampled_dir, true)?);
+    recordings.sort_by_key(|recording| recording.modified);
+
+    if let Some(max_age) = policy.get_max_age() {
+        let now = SystemTime::now();
+        let (expired, kept): (Vec<_>, Vec<_>) = recordings.into_iter().partition(|recording| {
+            !recording.downsampled && now.duration_since(recording.modified).is_ok_and(|age| age > max_age)
+        });
+        let mut failed = Vec::new();
+
+        for recording in expired {
+            if policy.get_downsample() && recording.is_video() {
+                match downsample_video(&recording.path, &downsampled_dir) {
+                    Ok(frames) => {
+                        log::info!("Downsampled {} to {} frames", recording.path.display(), frames);
+                        report.downsampled_videos += 1;
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to downsample {}, keeping it: {}", recording.path.display(), e);
+                        report.failed_downsamples += 1;
+                        failed.push(recording);
+                        continue;
+                    }
+                }
+            }
+            remove_recording(&recording, &mut report);
+        }
+
+        // Kept videos are the oldest, they still count towards the size and file limits
+        failed.extend(kept);
+        recordings = failed;
+    }
+
+    let mut total_bytes: u64 = recordings.iter().map(|recording| recording.size).sum();
+    let mut total_files = recordings.len();
+
+    for recording in recordings {
+        let over_bytes = policy.get_max_bytes().is_some_and(|max_bytes| total_bytes > max_bytes);
+        let over_files = policy.get_max_files().is_some_and(|max_files| total_files > max_files);
+        if !over_bytes && !over_files {
+            break;
+        }
+
+        total_bytes -= recording.size;
+        total_files -= 1;
+        remove_recording(&recording, &mut report);
+    }
+
+    Ok(report)
+}
+
+fn list_recordings(dir: &Path, downsampled: bool) -> Result<Vec<Recording>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut recordings = Vec::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let is_recording = path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| {
+            RECORDING_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+        });
+        let metadata = entry.metadata()?;
+        if !is_recording || !metadata.is_file() {
+            continue;
+        }
+
+        let mut recording = Recording {
+            path,
+            size: metadata.len(),
+            modified: metadata.modified()?,
+            downsampled,
+        };
+        if recording.is_video() {
+            recording.size += fs::metadata(ChunkManifest::path_for(&recording.path)).map_or(0, |m| m.len());
+        }
+        recordings.push(recording);
+    }
+
+    Ok(recordings)
+}
+
+fn remove_recording(recording: &Recording, report: &mut RetentionReport) {
+    if let Err(e) = fs::remove_file(&recording.path) {
+        log::error!("Failed to remove {}: {}", recording.path.display(), e);
+        return;
+    }
+    if recording.is_video() {
+        let _ = fs::remove_file(ChunkManifest::path_for(&recording.path));
+    }
+
+    log::info!("Removed {} ({} bytes)", recording.path.display(), recording.size);
+    report.deleted_files += 1;
+    report.deleted_bytes += recording.size;
+}
+
+/// Keeps the first frame of every minute of a video chunk as PNG in `output_dir`.
+fn downsample_video(path: &Path, output_dir: &Path) -> Result<usize> {
+    fs::create_dir_all(output_dir)?;
+
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut decoder = Mp4FrameDecoder::new(BufReader::new(file), size)?;
+    let manifest = ChunkManifest::read_for(path).ok().flatten();
+
+    let seconds_per_sample = match decoder.sample_count() {
+        0 => 0.0,
+        sample_count => decoder.duration().as_secs_f64() / sample_count as f64,
+    };
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+
+    let mut decoded_count = 0;
+    let mut last_minute = None;
+    let mut kept = 0;
+
+    while let Some(decoded) = decoder.next_frame()? {
+        let sample_index = decoded.sample_number.map_or(decoded_count, |i| i as usize - 1);
+        decoded_count += 1;
+
+        let captured_at = decoded.captured_at.or_else(|| {
+            manifest.as_ref().and_then(|manifest| manifest.frame(sample_index)).map(|frame| frame.captured_at)
+        });
+        // Videos without capture times are split into minutes from their start
+        let minute = match captured_at {
+            Some(captured_at) => captured_at.timestamp() / 60,
+            None => (sample_index as f64 * seconds_per_sample / 60.0) as i64,
+        };
+        if last_minute == Some(minute) {
+            continue;
+        }
+        last_minute = Some(minute);
+
+        let name = match captured_at {
+            Some(captured_at) => format!("{}-{}.png", stem, captured_at.format("%Y%m%d_%H%M")),
+            None => format!("{}-{}.png", stem, minute),
+        };
+        decoded.image.save_with_format(output_dir.join(name), image::ImageFormat::Png)?;
+        kept += 1;
+    }
+
+    Ok(kept)
+}
@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, DurationRound, Local, NaiveDateTime, TimeDelta, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+
+use super::{CaptureSchedule, ScheduleWindow};
+
+/// How far ahead a schedule is searched for its next change.
+const LOOKAHEAD_MINUTES: i64 = 8 * 24 * 60;
+
+const MONTH_NAMES: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Parsed `minute hour day-of-month month day-of-week` expression, one bit per matching value.
+pub(crate) struct CronExpression {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    weekdays: u64,
+    any_day_of_month: bool,
+    any_weekday: bool,
+}
+
+impl CronExpression {
+    pub fn parse(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minutes, hours, days_of_month, months, weekdays] = fields.as_slice() else {
+            return Err(anyhow!("Cron expression {:?} doesn't have 5 fields", s));
+        };
+
+        let mut weekday_bits = parse_field(weekdays, 0, 7, &WEEKDAY_NAMES)?;
+        // 7 is another name for sunday
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits = (weekday_bits | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59, &[])?,
+            hours: parse_field(hours, 0, 23, &[])?,
+            days_of_month: parse_field(days_of_month, 1, 31, &[])?,
+            months: parse_field(months, 1, 12, &MONTH_NAMES)?,
+            weekdays: weekday_bits,
+            // Like cron, `*/2` counts as unrestricted as well
+            any_day_of_month: days_of_month.starts_with('*'),
+            any_weekday: weekdays.starts_with('*'),
+        })
+    }
+
+    fn matches(&self, time: &NaiveDateTime) -> bool {
+        let has = |bits: u64, value: u32| bits & (1 << value) != 0;
+        let day_of_month = has(self.days_of_month, time.day());
+        let weekday = has(self.weekdays, time.weekday().num_days_from_sunday());
+        // Like cron, either day matches when both are restricted
+        let day = if !self.any_day_of_month && !self.any_weekday {
+            day_of_month || weekday
+        } else {
+            day_of_month && weekday
+        };
+
+        day && has(self.minutes, time.minute()) && has(self.hours, time.hour()) && has(self.months, time.month())
+    }
+}
+
+/// Bits of the values a cron field matches, e.g. `*`, `*/15`, `1-5`, `mon-fri` or `0,30`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64> {
+    let value = |s: &str| -> Result<u32> {
+        let value = match names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            Some(index) => min + index as u32,
+            None => s.parse::<u32>().map_err(|_| anyhow!("Invalid cron value {:?}", s))?,
+        };
+        if !(min..=max).contains(&value) {
+            return Err(anyhow!("Cron value {} is not within {}-{}", value, min, max));
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| anyhow!("Invalid cron step {:?}", step))?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(anyhow!("Cron step of {:?} is 0", field));
+        }
+
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (value(first)?, value(last)?),
+            // `5/10` runs from 5 to the end like cron does
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if first > last {
+            return Err(anyhow!("Cron range {:?} is backwards", range));
+        }
+        for value in (first..=last).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+enum Window {
+    Weekly { days: Vec<Weekday>, start_minute: u32, end_minute: u32 },
+    Cron(CronExpression),
+}
+
+impl Window {
+    fn contains(&self, time: &NaiveDateTime) -> bool {
+        match self {
+            Window::Weekly { days, start_minute, end_minute } => {
+                let minute = time.hour() * 60 + time.minute();
+                let day = time.weekday();
+                if start_minute < end_minute {
+                    days.contains(&day) && (*start_minute..*end_minute).contains(&minute)
+                } else {
+                    // Past midnight, the part after midnight belongs to the day before
+                    (days.contains(&day) && minute >= *start_minute)
+                        || (days.contains(&day.pred()) && minute < *end_minute)
+                }
+            }
+            Window::Cron(expression) => expression.matches(time),
+        }
+    }
+}
+
+/// Compiled form of a [`CaptureSchedule`], tells whether capturing is allowed at a given time.
+pub struct Schedule {
+    timezone: Option<Tz>,
+    windows: Vec<Window>,
+}
+
+impl Schedule {
+    pub fn new(schedule: &CaptureSchedule) -> Result<Self> {
+        let timezone = schedule
+            .get_timezone()
+            .map(|timezone| timezone.parse::<Tz>().map_err(|e| anyhow!("Unknown time zone {:?}: {}", timezone, e)))
+            .transpose()?;
+        let windows = schedule
+            .get_windows()
+            .into_iter()
+            .map(|window| {
+                Ok(match window {
+                    ScheduleWindow::Weekly { days, start_minute, end_minute } => {
+                        Window::Weekly { days, start_minute, end_minute }
+                    }
+                    ScheduleWindow::Cron(expression) => Window::Cron(CronExpression::parse(&expression)?),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { timezone, windows })
+    }
+
+    /// Whether capturing is allowed at `at`, always if the schedule has no windows.
+    pub fn is_active(&self, at: DateTime<Utc>) -> bool {
+        if self.windows.is_empty() {
+            return true;
+        }
+        let time = match self.timezone {
+            Some(timezone) => at.with_timezone(&timezone).naive_local(),
+            None => at.with_timezone(&Local).naive_local(),
+        };
+        self.windows.iter().any(|window| window.contains(&time))
+    }
+
+    /// Next time capturing starts or stops after `at`, `None` if that doesn't happen within 8 days.
+    pub fn next_change(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let active = self.is_active(at);
+        let mut minute = at.duration_trunc(TimeDelta::minutes(1)).ok()?;
+        for _ in 0..LOOKAHEAD_MINUTES {
+            minute += TimeDelta::minutes(1);
+            if self.is_active(minute) != active {
+                return Some(minute);
+            }
+        }
+        None
+    }
+}
@@ -1,30 +1,101 @@
-use image::DynamicImage;
-use openh264::encoder::Encoder;
-use std::path::Path;
-use xcap::Monitor;
 use anyhow::Result;
+use chrono::{DateTime, Local};
+use image::{imageops, DynamicImage, GenericImageView, RgbImage};
+use openh264::encoder::{
+    BitRate, Encoder, EncoderConfig, FrameRate, IntraFramePeriod, QpRange, RateControlMode, UsageType,
+};
+use openh264::OpenH264API;
+use std::path::{Path, PathBuf};
+
+use super::mp4_writer::{Mp4ChunkWriter, TIMESCALE};
+use super::{EncoderSettings, OddSizePolicy, RateControl};
+
 pub struct ScreenCapturer {
     encoder: Encoder,
+    chunk_path: Option<PathBuf>,
+    writer: Option<Mp4ChunkWriter>,
     buf: Vec<u8>,
     frame_count: u32,
+    /// Size of the frames of the current chunk, before making it fit for the codec
+    frame_size: Option<(u32, u32)>,
+    settings: EncoderSettings,
+    fps: f32,
+    fragment_frames: Option<u32>,
 }
 
 impl ScreenCapturer {
-    pub fn new() -> Self {
+    pub fn new(settings: EncoderSettings, fps: f32, fragment_frames: Option<u32>) -> Self {
         Self {
-            encoder: Encoder::new().unwrap(),
+            encoder: create_encoder(&settings, fps),
+            chunk_path: None,
+            writer: None,
             buf: Vec::new(),
             frame_count: 0,
+            frame_size: None,
+            settings,
+            fps,
+            fragment_frames,
         }
     }
 
     pub fn is_buf_empty(&self) -> bool {
-        self.buf.len() == 0
+        self.frame_count == 0
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.chunk_path.is_some()
+    }
+
+    /// Size of the frames of the current chunk, every frame of a chunk has to have it.
+    pub fn frame_size(&self) -> Option<(u32, u32)> {
+        self.frame_size
+    }
+
+    /// Size of the video of the current chunk, the frame size made even.
+    pub fn coded_size(&self) -> Option<(u32, u32)> {
+        self.frame_size.map(|(width, height)| self.settings.coded_size(width, height))
+    }
+
+    /// Starts a new chunk, encoded frames are appended to the file at `p` as they come in.
+    pub fn start(&mut self, p: &Path) {
+        self.chunk_path = Some(p.to_path_buf());
     }
 
-    pub fn frame(&mut self, image: &DynamicImage) {
+    pub fn frame(&mut self, image: &DynamicImage, captured_at: DateTime<Local>) -> Result<()> {
         use openh264::formats::*;
-        let frame = image.to_rgb8();
+        let size = image.dimensions();
+        if let Some(frame_size) = self.frame_size.filter(|frame_size| *frame_size != size) {
+            return Err(anyhow::anyhow!(
+                "Frame of {}x{} doesn't fit into a chunk of {}x{}, it needs a new chunk",
+                size.0,
+                size.1,
+                frame_size.0,
+                frame_size.1
+            ));
+        }
+        let frame = to_coded_size(image.to_rgb8(), &self.settings);
+        if frame.width() == 0 || frame.height() == 0 {
+            return Err(anyhow::anyhow!("Frame of {}x{} is too small to encode", size.0, size.1));
+        }
+
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => {
+                let Some(path) = &self.chunk_path else {
+                    return Err(anyhow::anyhow!("No video chunk started"));
+                };
+                // The video takes the size of the first frame, which may be scaled or cropped
+                self.frame_size = Some(size);
+                self.writer.insert(Mp4ChunkWriter::create(
+                    path,
+                    frame.width(),
+                    frame.height(),
+                    self.fragment_frames,
+                    (TIMESCALE as f32 / self.fps).round() as u32,
+                )?)
+            }
+        };
+
         // Convert RGB into YUV.
         let rgb_source = RgbSliceU8::new(
             frame.as_raw(),
@@ -32,117 +103,87 @@ impl ScreenCapturer {
         );
         let yuv = YUVBuffer::from_rgb_source(rgb_source);
 
-        // Forces the encoder to emit an intra frame (I-frame, "keyframe") for the next encoded frame
-        self.encoder.force_intra_frame();
-
-        // Encode YUV into H.264.
-        let bitstream = self.encoder.encode(&yuv).unwrap();
+        // Encode YUV into H.264, keyframes are inserted according to the configured interval
+        // and every chunk starts with one since the encoder is recreated per chunk.
+        let bitstream = self.encoder.encode(&yuv)?;
+        self.buf.clear();
         bitstream.write_vec(&mut self.buf);
 
+        writer.write_sample(&self.buf, captured_at)?;
+
         log::info!(
-            "Encoded frame {}, buf size {}",
+            "Encoded frame {}, frame size {}",
             self.frame_count,
             self.buf.len()
         );
 
         self.frame_count += 1;
+        Ok(())
     }
 
-    pub fn save(&mut self, p: &Path, fps: f32) {
-        use minimp4::Mp4Muxer;
-        use std::io::{Cursor, Read, Seek, SeekFrom};
-
-        let monitor = get_primary_monitor();
-
-        let mut video_buffer = Cursor::new(Vec::new());
-        let mut mp4muxer = Mp4Muxer::new(&mut video_buffer);
-        mp4muxer.init_video(
-            monitor.width() as i32,
-            monitor.height() as i32,
-            false,
-            "Screen capturer",
-        );
-
-        mp4muxer.write_video_with_fps(&self.buf, fps as u32);
-        mp4muxer.close();
-
-        video_buffer.seek(SeekFrom::Start(0)).unwrap();
-        let mut video_bytes = Vec::new();
-        video_buffer.read_to_end(&mut video_bytes).unwrap();
-
-        std::fs::write(p, &video_bytes).unwrap();
-
-        log::info!("Saved {} frames to {}", self.frame_count, p.display());
+    /// Finishes the current chunk by writing the index of all frames to the end of the file.
+    pub fn save(&mut self) -> Result<Option<PathBuf>> {
+        let writer = self.writer.take();
+        let frame_count = self.frame_count;
 
         // reset
-        self.encoder = Encoder::new().unwrap();
-        self.buf.clear();
+        self.encoder = create_encoder(&self.settings, self.fps);
+        self.chunk_path = None;
         self.frame_count = 0;
+        self.frame_size = None;
+
+        let path = writer.map(|writer| writer.finish()).transpose()?.flatten();
+        if let Some(path) = &path {
+            log::info!("Saved {} frames to {}", frame_count, path.display());
+        }
+        Ok(path)
     }
 }
 
-fn get_monitor(monitor_id: u32) -> Monitor {
-    Monitor::all()
-        .unwrap()
-        .into_iter()
-        .find(|m| m.id() == monitor_id)
-        .ok_or_else(|| anyhow::anyhow!("Monitor not found"))
-        .unwrap()
-}
+/// Pads or crops `frame` to the even size H.264 needs for its 4:2:0 chroma planes.
+fn to_coded_size(frame: RgbImage, settings: &EncoderSettings) -> RgbImage {
+    let (width, height) = frame.dimensions();
+    let (coded_width, coded_height) = settings.coded_size(width, height);
+    if (coded_width, coded_height) == (width, height) {
+        return frame;
+    }
 
-fn get_primary_monitor_id() -> u32 {
-    Monitor::all()
-        .unwrap()
-        .iter()
-        .find(|m| m.is_primary())
-        .unwrap()
-        .id()
+    match settings.get_odd_size() {
+        // Repeating the edge keeps the extra pixels cheap to encode
+        OddSizePolicy::Pad => RgbImage::from_fn(coded_width, coded_height, |x, y| {
+            *frame.get_pixel(x.min(width - 1), y.min(height - 1))
+        }),
+        OddSizePolicy::Crop => imageops::crop_imm(&frame, 0, 0, coded_width, coded_height).to_image(),
+    }
 }
 
-pub fn get_primary_monitor() -> Monitor {
-    get_monitor(get_primary_monitor_id())
+impl From<RateControl> for RateControlMode {
+    fn from(rate_control: RateControl) -> Self {
+        match rate_control {
+            RateControl::Quality => RateControlMode::Quality,
+            RateControl::Bitrate => RateControlMode::Bitrate,
+            RateControl::Buffer => RateControlMode::Bufferbased,
+            RateControl::Timestamp => RateControlMode::Timestamp,
+            RateControl::Off => RateControlMode::Off,
+        }
+    }
 }
 
-// pub async fn get_screenshot() -> Result<DynamicImage> {
-//     let image = std::thread::spawn(move || -> Result<DynamicImage> {
-//         let monitor = get_primary_monitor();
-//         let image = monitor
-//             .capture_image()
-//             .map_err(anyhow::Error::from)
-//             .map(DynamicImage::ImageRgba8)?;
-//         Ok(image)
-//     })
-//     .join()
-//     .unwrap()?;
-//     Ok(image)
-// }
-
-pub async fn get_screenshot(quality: u8) -> Result<DynamicImage> {
-    let quality = quality.clamp(1, 100);
-
-    let image = std::thread::spawn(move || -> Result<DynamicImage> {
-        let monitor = get_primary_monitor();
-        let original = monitor
-            .capture_image()
-            .map_err(anyhow::Error::from)
-            .map(DynamicImage::ImageRgba8)?;
-
-        // Convert quality (1-100) to scale factor
-        // 100 = original size (scale by 1.0)
-        // 50 = half size (scale by 0.5)
-        // 1 = smallest (scale by 0.01)
-        let scale = quality as f32 / 100.0;
-        
-        let scaled = original.resize(
-            (original.width() as f32 * scale) as u32,
-            (original.height() as f32 * scale) as u32,
-            image::imageops::FilterType::Nearest
-        );
-
-        Ok(scaled)
-    })
-    .join()
-    .unwrap()?;
-
-    Ok(image)
-}
\ No newline at end of file
+fn create_encoder(settings: &EncoderSettings, fps: f32) -> Encoder {
+    let config = EncoderConfig::new()
+        .usage_type(UsageType::ScreenContentRealTime)
+        // openh264 rejects rates below 1 fps, the MP4 sample durations carry the real timing
+        .max_frame_rate(FrameRate::from_hz(fps.max(1.0)))
+        .bitrate(BitRate::from_bps(settings.get_bitrate()))
+        .rate_control_mode(settings.get_rate_control().into())
+        .intra_frame_period(IntraFramePeriod::from_num_frames(settings.get_keyframe_interval()))
+        // Skipped frames would leave holes in the chunk, every captured frame has to end up in the video
+        .skip_frames(false);
+
+    let config = match settings.get_max_qp() {
+        Some(max_qp) => config.qp(QpRange::new(0, max_qp)),
+        None => config,
+    };
+
+    Encoder::with_api_config(OpenH264API::from_source(), config).unwrap()
+}
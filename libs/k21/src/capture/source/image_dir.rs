@@ -0,0 +1,41 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use image::ImageFormat;
+
+use crate::capture::CapturedFrame;
+
+use super::FrameSource;
+
+/// Replays the images of a directory in file name order, one image per tick.
+pub struct ImageDirectorySource {
+    files: VecDeque<PathBuf>,
+}
+
+impl ImageDirectorySource {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut files = std::fs::read_dir(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read image directory {}: {}", path.display(), e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && ImageFormat::from_path(p).is_ok())
+            .collect::<Vec<_>>();
+        files.sort();
+
+        log::info!("Found {} images in {}", files.len(), path.display());
+        Ok(Self { files: files.into() })
+    }
+}
+
+impl FrameSource for ImageDirectorySource {
+    fn next_frames(&mut self, frame_number: u64) -> Result<Option<Vec<CapturedFrame>>> {
+        let Some(path) = self.files.pop_front() else {
+            return Ok(None);
+        };
+
+        let image = image::open(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to open image {}: {}", path.display(), e))?;
+        Ok(Some(vec![CapturedFrame::new(frame_number, image)]))
+    }
+}
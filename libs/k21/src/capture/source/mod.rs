@@ -0,0 +1,20 @@
+mod types;
+pub use types::FrameSource;
+
+mod utils;
+pub(crate) use utils::create_frame_source;
+
+mod monitor;
+pub use monitor::MonitorSource;
+
+mod window;
+pub use window::WindowSource;
+
+mod image_dir;
+pub use image_dir::ImageDirectorySource;
+
+mod video;
+pub use video::Mp4FileSource;
+
+mod synthetic;
+pub use synthetic::SyntheticSource;
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use image::DynamicImage;
+use xcap::Monitor;
+
+use crate::capture::{CapturedFrame, MonitorInfo, MonitorSelection};
+
+use super::FrameSource;
+
+/// Captures whole monitors through xcap.
+pub struct MonitorSource {
+    selection: MonitorSelection,
+}
+
+impl MonitorSource {
+    pub fn new(selection: MonitorSelection) -> Self {
+        Self { selection }
+    }
+}
+
+impl FrameSource for MonitorSource {
+    fn next_frames(&mut self, frame_number: u64) -> Result<Option<Vec<CapturedFrame>>> {
+        // Monitors are looked up on every tick since xcap monitors are not Send on every platform
+        // and monitors may be plugged in or out while capturing
+        let mut frames = Vec::new();
+        let mut last_error = None;
+        for monitor in get_monitors(&self.selection)? {
+            // One failing monitor doesn't cost the frames of the others
+            match monitor.capture_image() {
+                Ok(image) => frames.push(
+                    CapturedFrame::new(frame_number, DynamicImage::ImageRgba8(image)).with_monitor(monitor_info(&monitor)),
+                ),
+                Err(e) => {
+                    log::warn!("Failed to capture monitor {} ({}): {}", monitor.id(), monitor.name(), e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        if let Some(e) = last_error.filter(|_| frames.is_empty()) {
+            return Err(e.into());
+        }
+        Ok(Some(frames))
+    }
+}
+
+fn get_monitors(selection: &MonitorSelection) -> Result<Vec<Monitor>> {
+    let monitors: Vec<Monitor> = Monitor::all()?
+        .into_iter()
+        .filter(|m| selection.matches(m.id(), m.name(), m.is_primary()))
+        .collect();
+
+    if monitors.is_empty() {
+        return Err(anyhow::anyhow!("No monitor matches {:?}", selection));
+    }
+    Ok(monitors)
+}
+
+pub(crate) fn monitor_info(monitor: &Monitor) -> MonitorInfo {
+    MonitorInfo {
+        id: monitor.id(),
+        name: monitor.name().to_string(),
+        x: monitor.x(),
+        y: monitor.y(),
+        width: monitor.width(),
+        height: monitor.height(),
+        scale_factor: monitor.scale_factor(),
+    }
+}
@@ -0,0 +1,39 @@
+use anyhow::Result;
+use image::{DynamicImage, Rgb, RgbImage};
+
+use crate::capture::CapturedFrame;
+
+use super::FrameSource;
+
+/// Generates frames without a display: a gradient with a bar moving a bit on every frame,
+/// so consecutive frames always differ.
+pub struct SyntheticSource {
+    width: u32,
+    height: u32,
+}
+
+impl SyntheticSource {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width: width.max(1),
+            height: height.max(1),
+        }
+    }
+}
+
+impl FrameSource for SyntheticSource {
+    fn next_frames(&mut self, frame_number: u64) -> Result<Option<Vec<CapturedFrame>>> {
+        let bar_width = (self.width / 8).max(1);
+        let bar_start = ((frame_number * bar_width as u64) % self.width as u64) as u32;
+
+        let image = RgbImage::from_fn(self.width, self.height, |x, y| {
+            if x >= bar_start && x < bar_start + bar_width {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([(x * 255 / self.width) as u8, (y * 255 / self.height) as u8, 128])
+            }
+        });
+
+        Ok(Some(vec![CapturedFrame::new(frame_number, DynamicImage::ImageRgb8(image))]))
+    }
+}
@@ -0,0 +1,12 @@
+use anyhow::Result;
+
+use crate::capture::CapturedFrame;
+
+/// Where the capture loop pulls its frames from.
+///
+/// The capture loop calls `next_frames` once per tick on a blocking thread. A source may
+/// return several frames per tick, one for every stream it produces (e.g. one per monitor).
+/// Returning `Ok(None)` tells the loop that the source is exhausted and ends the capture.
+pub trait FrameSource: Send {
+    fn next_frames(&mut self, frame_number: u64) -> Result<Option<Vec<CapturedFrame>>>;
+}
@@ -0,0 +1,16 @@
+use anyhow::Result;
+
+use crate::capture::{CaptureTarget, ScreenCaptureConfig};
+
+use super::{FrameSource, ImageDirectorySource, MonitorSource, Mp4FileSource, SyntheticSource, WindowSource};
+
+pub(crate) fn create_frame_source(config: &ScreenCaptureConfig) -> Result<Box<dyn FrameSource>> {
+    let source: Box<dyn FrameSource> = match config.get_target() {
+        CaptureTarget::Monitors => Box::new(MonitorSource::new(config.get_monitors())),
+        CaptureTarget::Windows(selector) => Box::new(WindowSource::new(&selector)?),
+        CaptureTarget::ImageDirectory(path) => Box::new(ImageDirectorySource::open(&path)?),
+        CaptureTarget::Video(path) => Box::new(Mp4FileSource::open(&path)?),
+        CaptureTarget::Synthetic { width, height } => Box::new(SyntheticSource::new(width, height)),
+    };
+    Ok(source)
+}
@@ -0,0 +1,40 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::capture::CapturedFrame;
+use crate::upload::Mp4FrameDecoder;
+
+use super::FrameSource;
+
+/// Replays the frames of an existing MP4 recording, one decoded frame per tick.
+pub struct Mp4FileSource {
+    decoder: Mp4FrameDecoder<BufReader<File>>,
+}
+
+impl Mp4FileSource {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path.as_ref())?;
+        let size = file.metadata()?.len();
+        let decoder = Mp4FrameDecoder::new(BufReader::new(file), size)?;
+        Ok(Self { decoder })
+    }
+}
+
+impl FrameSource for Mp4FileSource {
+    fn next_frames(&mut self, frame_number: u64) -> Result<Option<Vec<CapturedFrame>>> {
+        Ok(self
+            .decoder
+            .next_frame()?
+            .map(|decoded| {
+                let frame = CapturedFrame::new(frame_number, decoded.image);
+                // Keep the original capture time when replaying a recording
+                match decoded.captured_at {
+                    Some(captured_at) => vec![frame.with_captured_at(captured_at)],
+                    None => vec![frame],
+                }
+            }))
+    }
+}
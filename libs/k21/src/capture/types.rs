@@ -1,29 +1,939 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, Weekday};
+use image::DynamicImage;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::{BackpressurePolicy, FrameEncoding};
+use crate::image2text::OcrConfig;
 
 const FPS_DEFAULT: f32 = 1.0;
+const FRAME_QUEUE_CAPACITY_DEFAULT: usize = 32;
 const DURATION_DEFAULT: u64 = 1;
 
 const QUALITY_DEFAULT: u8 = 100;
+const COMPRESSION_QUALITY_DEFAULT: u8 = 80;
+
+const ADAPTIVE_MIN_FPS_DEFAULT: f32 = 0.2;
+const ADAPTIVE_MAX_FPS_DEFAULT: f32 = 5.0;
+const ADAPTIVE_THRESHOLD_DEFAULT: f32 = 0.05;
+
+const KEYFRAME_INTERVAL_DEFAULT: u32 = 60;
+const BITRATE_DEFAULT: u32 = 2_000_000;
+const MAX_QP_LIMIT: u8 = 51;
+
+/// Rate control of the H.264 encoder, mirrors openh264's `RateControlMode`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum RateControl {
+    /// Constant quality, bitrate varies with the content
+    #[default]
+    Quality,
+    /// Aim for the target bitrate
+    Bitrate,
+    /// Keep the encoder buffer from overflowing
+    Buffer,
+    /// Rate control based on frame timestamps
+    Timestamp,
+    Off,
+}
+
+impl std::str::FromStr for RateControl {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "quality" => Ok(RateControl::Quality),
+            "bitrate" => Ok(RateControl::Bitrate),
+            "buffer" => Ok(RateControl::Buffer),
+            "timestamp" => Ok(RateControl::Timestamp),
+            "off" => Ok(RateControl::Off),
+            _ => bail!("Unknown rate control {:?}", s),
+        }
+    }
+}
+
+/// How frames with an odd width or height are made fit for H.264, which needs even sizes.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum OddSizePolicy {
+    /// Repeat the last column or row, processors crop it off again using the chunk manifest
+    #[default]
+    Pad,
+    /// Drop the last column or row
+    Crop,
+}
+
+impl std::str::FromStr for OddSizePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "pad" => Ok(OddSizePolicy::Pad),
+            "crop" => Ok(OddSizePolicy::Crop),
+            _ => bail!("Unknown odd size policy {:?}", s),
+        }
+    }
+}
+
+/// H.264 encoder settings for recorded video chunks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct EncoderSettings {
+    pub keyframe_interval: Option<u32>, // frames between two keyframes
+    pub bitrate: Option<u32>, // target bits per second
+    pub rate_control: Option<RateControl>,
+    pub max_qp: Option<u8>, // upper bound of the quantizer (0-51), lower means better quality
+    pub odd_size: Option<OddSizePolicy>,
+}
+
+impl EncoderSettings {
+    pub fn new(keyframe_interval: Option<u32>, bitrate: Option<u32>, rate_control: Option<RateControl>, max_qp: Option<u8>) -> Self {
+        Self {
+            keyframe_interval,
+            bitrate,
+            rate_control,
+            max_qp,
+            odd_size: None,
+        }
+    }
+
+    pub fn with_odd_size(mut self, odd_size: OddSizePolicy) -> Self {
+        self.odd_size = Some(odd_size);
+        self
+    }
+
+    pub fn get_keyframe_interval(&self) -> u32 {
+        self.keyframe_interval.unwrap_or(KEYFRAME_INTERVAL_DEFAULT).max(1)
+    }
+
+    pub fn get_bitrate(&self) -> u32 {
+        self.bitrate.unwrap_or(BITRATE_DEFAULT)
+    }
+
+    pub fn get_rate_control(&self) -> RateControl {
+        self.rate_control.unwrap_or_default()
+    }
+
+    pub fn get_max_qp(&self) -> Option<u8> {
+        self.max_qp.map(|qp| qp.min(MAX_QP_LIMIT))
+    }
+
+    pub fn get_odd_size(&self) -> OddSizePolicy {
+        self.odd_size.unwrap_or_default()
+    }
+
+    /// Size of the video for frames of the given size, both even.
+    pub fn coded_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let even = |size: u32| match self.get_odd_size() {
+            OddSizePolicy::Pad => size + size % 2,
+            OddSizePolicy::Crop => size - size % 2,
+        };
+        (even(width), even(height))
+    }
+}
+
+/// Capture rate that follows what happens on screen: it decays to `min_fps` while the screen is
+/// static and jumps to `max_fps` as soon as a frame differs from the previous one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct AdaptiveFps {
+    pub min_fps: Option<f32>,
+    pub max_fps: Option<f32>,
+    pub threshold: Option<f32>, // share of changed pixels (0-1) that counts as a change
+}
+
+impl AdaptiveFps {
+    pub fn new(min_fps: Option<f32>, max_fps: Option<f32>, threshold: Option<f32>) -> Self {
+        Self {
+            min_fps,
+            max_fps,
+            threshold,
+        }
+    }
+
+    pub fn get_min_fps(&self) -> f32 {
+        self.min_fps.unwrap_or(ADAPTIVE_MIN_FPS_DEFAULT)
+    }
+
+    pub fn get_max_fps(&self) -> f32 {
+        self.max_fps.unwrap_or(ADAPTIVE_MAX_FPS_DEFAULT).max(self.get_min_fps())
+    }
+
+    pub fn get_threshold(&self) -> f32 {
+        self.threshold.unwrap_or(ADAPTIVE_THRESHOLD_DEFAULT)
+    }
+
+    /// Checks that both rates are positive and `max_fps` isn't below `min_fps`.
+    pub fn validate(&self) -> Result<()> {
+        for (name, fps) in [("minimum", self.min_fps), ("maximum", self.max_fps)] {
+            if fps.is_some_and(|fps| !(fps.is_finite() && fps > 0.0)) {
+                bail!("Adaptive {} fps must be positive, got {}", name, fps.unwrap_or_default());
+            }
+        }
+        if let (Some(min_fps), Some(max_fps)) = (self.min_fps, self.max_fps) {
+            if max_fps < min_fps {
+                bail!("Adaptive maximum fps {} is below the minimum fps {}", max_fps, min_fps);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Format of the frames written to stdout.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum StdoutFormat {
+    /// k21 frame stream with capture metadata, see [`super::FrameEncoder`]
+    #[default]
+    K21,
+    /// YUV4MPEG2 for ffmpeg and gstreamer, only the first stream is written
+    Y4m,
+}
+
+impl std::str::FromStr for StdoutFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "k21" => Ok(StdoutFormat::K21),
+            "y4m" | "yuv4mpeg2" | "yuv4mpegpipe" => Ok(StdoutFormat::Y4m),
+            _ => bail!("Unknown stdout format {:?}", s),
+        }
+    }
+}
+
+/// File format of saved screenshots.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum ScreenshotFormat {
+    #[default]
+    Png,
+    Jpeg,
+    WebP,
+    WebPLossless,
+}
+
+impl ScreenshotFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::Jpeg => "jpg",
+            ScreenshotFormat::WebP | ScreenshotFormat::WebPLossless => "webp",
+        }
+    }
+}
+
+impl std::str::FromStr for ScreenshotFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "png" => Ok(ScreenshotFormat::Png),
+            "jpg" | "jpeg" => Ok(ScreenshotFormat::Jpeg),
+            "webp" => Ok(ScreenshotFormat::WebP),
+            "webp-lossless" | "webp_lossless" => Ok(ScreenshotFormat::WebPLossless),
+            _ => bail!("Unknown screenshot format {:?}", s),
+        }
+    }
+}
+
+/// Filter used when frames are scaled down by `quality`, smoother filters keep text more readable for OCR.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum ResizeFilter {
+    #[default]
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl std::str::FromStr for ResizeFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "nearest" => Ok(ResizeFilter::Nearest),
+            "triangle" | "linear" => Ok(ResizeFilter::Triangle),
+            "catmullrom" | "catmull-rom" | "cubic" => Ok(ResizeFilter::CatmullRom),
+            "gaussian" => Ok(ResizeFilter::Gaussian),
+            "lanczos3" | "lanczos" => Ok(ResizeFilter::Lanczos3),
+            _ => bail!("Unknown resize filter {:?}", s),
+        }
+    }
+}
+
+impl From<ResizeFilter> for image::imageops::FilterType {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => image::imageops::FilterType::Nearest,
+            ResizeFilter::Triangle => image::imageops::FilterType::Triangle,
+            ResizeFilter::CatmullRom => image::imageops::FilterType::CatmullRom,
+            ResizeFilter::Gaussian => image::imageops::FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => image::imageops::FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Limits for the files kept in an output directory, the oldest recordings go first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RetentionPolicy {
+    pub max_bytes: Option<u64>, // total size of all recordings
+    pub max_age: Option<u64>, // seconds
+    pub max_files: Option<usize>,
+    pub downsample: Option<bool>, // keep one frame per minute of expired videos as PNG instead of deleting them
+}
+
+impl RetentionPolicy {
+    pub fn new(max_bytes: Option<u64>, max_age: Option<u64>, max_files: Option<usize>, downsample: Option<bool>) -> Self {
+        Self {
+            max_bytes,
+            max_age,
+            max_files,
+            downsample,
+        }
+    }
+
+    pub fn get_max_bytes(&self) -> Option<u64> {
+        self.max_bytes
+    }
+
+    pub fn get_max_age(&self) -> Option<std::time::Duration> {
+        self.max_age.map(std::time::Duration::from_secs)
+    }
+
+    pub fn get_max_files(&self) -> Option<usize> {
+        self.max_files
+    }
+
+    pub fn get_downsample(&self) -> bool {
+        self.downsample.unwrap_or(false)
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_bytes.is_none() && self.max_age.is_none() && self.max_files.is_none()
+    }
+}
+
+/// How redacted areas are made unreadable.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub enum RedactionStyle {
+    #[default]
+    BlackOut,
+    Blur,
+}
+
+impl std::str::FromStr for RedactionStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "black" | "blackout" | "black-out" => Ok(RedactionStyle::BlackOut),
+            "blur" => Ok(RedactionStyle::Blur),
+            _ => bail!("Unknown redaction style {:?}", s),
+        }
+    }
+}
+
+/// Text that is redacted wherever OCR finds it on screen.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum SensitivePattern {
+    /// The value after a label like `Password:` or `secret=`
+    Password,
+    /// 13 to 19 digit numbers passing the Luhn check
+    CardNumber,
+    /// Well-known API key and token formats, e.g. `sk-...`, `AKIA...` or `ghp_...`
+    ApiKey,
+    /// A regex, its first capture group is redacted if it has one, the whole match otherwise
+    Custom(String),
+}
+
+impl SensitivePattern {
+    pub fn name(&self) -> &str {
+        match self {
+            SensitivePattern::Password => "password",
+            SensitivePattern::CardNumber => "card-number",
+            SensitivePattern::ApiKey => "api-key",
+            SensitivePattern::Custom(pattern) => pattern,
+        }
+    }
+
+    pub(crate) fn regex(&self) -> Result<Regex> {
+        let pattern = match self {
+            SensitivePattern::Password => r"(?i)\b(?:password|passwd|passwort|pwd|secret|pin)\s*[:=]\s*(\S+)",
+            SensitivePattern::CardNumber => r"\b\d(?:[ -]?\d){12,18}\b",
+            SensitivePattern::ApiKey => {
+                r"\b(?:sk|pk|rk)[-_][A-Za-z0-9_-]{16,}|\bAKIA[0-9A-Z]{16}\b|\bgh[pousr]_[A-Za-z0-9]{30,}|\bxox[abprs]-[A-Za-z0-9-]{10,}"
+            }
+            SensitivePattern::Custom(pattern) => pattern,
+        };
+        Regex::new(pattern).map_err(|e| anyhow::anyhow!("Invalid redaction pattern {}: {}", pattern, e))
+    }
+}
+
+impl From<&str> for SensitivePattern {
+    fn from(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "password" => SensitivePattern::Password,
+            "card" | "card-number" => SensitivePattern::CardNumber,
+            "api-key" | "apikey" => SensitivePattern::ApiKey,
+            _ => SensitivePattern::Custom(s.to_string()),
+        }
+    }
+}
+
+impl From<String> for SensitivePattern {
+    fn from(s: String) -> Self {
+        SensitivePattern::from(s.as_str())
+    }
+}
+
+/// What to black out or blur in every frame before it is encoded, saved or sent anywhere.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RedactionConfig {
+    pub style: Option<RedactionStyle>,
+    /// Areas of every monitor or window
+    pub regions: Option<Vec<CaptureRegion>>,
+    /// Windows redacted wherever they show up on a captured monitor
+    pub windows: Option<Vec<WindowSelector>>,
+    /// Text found with OCR, this runs OCR on every frame
+    pub patterns: Option<Vec<SensitivePattern>>,
+    /// JSON lines file every redaction is recorded in, without the redacted content
+    pub audit_log: Option<String>,
+    /// OCR that finds the patterns, the default OCR config if `None`
+    pub ocr: Option<OcrConfig>,
+}
+
+impl RedactionConfig {
+    pub fn get_style(&self) -> RedactionStyle {
+        self.style.unwrap_or_default()
+    }
+
+    pub fn get_regions(&self) -> Vec<CaptureRegion> {
+        self.regions.clone().unwrap_or_default()
+    }
+
+    pub fn get_windows(&self) -> Vec<WindowSelector> {
+        self.windows.clone().unwrap_or_default()
+    }
+
+    pub fn get_patterns(&self) -> Vec<SensitivePattern> {
+        self.patterns.clone().unwrap_or_default()
+    }
+
+    pub fn get_audit_log(&self) -> Option<String> {
+        self.audit_log.clone()
+    }
+
+    pub fn get_ocr(&self) -> OcrConfig {
+        self.ocr.clone().unwrap_or_else(OcrConfig::default)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.get_regions().is_empty() && self.get_windows().is_empty() && self.get_patterns().is_empty()
+    }
+}
+
+/// When capturing is allowed. Outside of its windows capturing pauses and open video chunks are closed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct CaptureSchedule {
+    /// IANA time zone of the windows like `Europe/Berlin`, the system time zone if `None`
+    pub timezone: Option<String>,
+    pub windows: Option<Vec<ScheduleWindow>>,
+}
+
+impl CaptureSchedule {
+    pub fn new(timezone: Option<String>, windows: Vec<ScheduleWindow>) -> Self {
+        Self { timezone, windows: Some(windows) }
+    }
+
+    pub fn get_timezone(&self) -> Option<String> {
+        self.timezone.clone()
+    }
+
+    pub fn get_windows(&self) -> Vec<ScheduleWindow> {
+        self.windows.clone().unwrap_or_default()
+    }
+}
+
+/// A time span capturing is allowed in, with minute precision.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ScheduleWindow {
+    /// Time of day on some weekdays, the end may be past midnight of the next day
+    Weekly {
+        days: Vec<Weekday>,
+        /// Minutes since midnight
+        start_minute: u32,
+        /// Minutes since midnight, 1440 for the end of the day
+        end_minute: u32,
+    },
+    /// Cron-like `minute hour day-of-month month day-of-week`, capturing runs in every minute it matches
+    Cron(String),
+}
+
+/// Parses cron expressions like `* 9-16 * * mon-fri`, or weekday and time ranges like
+/// `mon-fri 09:00-17:00`, `sat,sun 10:00-14:00`, `22:00-06:00` (every day) or `mon-fri` (all day).
+impl std::str::FromStr for ScheduleWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        if parts.len() == 5 {
+            super::schedule::CronExpression::parse(s)?;
+            return Ok(ScheduleWindow::Cron(parts.join(" ")));
+        }
+
+        let (days, times) = match parts.as_slice() {
+            [days, times] => (parse_weekdays(days)?, Some(*times)),
+            [part] if part.contains(':') => (ALL_WEEKDAYS.to_vec(), Some(*part)),
+            [days] => (parse_weekdays(days)?, None),
+            _ => return Err(anyhow::anyhow!("Schedule {:?} is neither [days] hh:mm-hh:mm nor a cron expression", s)),
+        };
+        let (start_minute, end_minute) = match times {
+            Some(times) => {
+                let (start, end) = times
+                    .split_once('-')
+                    .ok_or_else(|| anyhow::anyhow!("Schedule times {:?} are not hh:mm-hh:mm", times))?;
+                (parse_minute_of_day(start)?, parse_minute_of_day(end)?)
+            }
+            None => (0, MINUTES_PER_DAY),
+        };
+        if start_minute == end_minute {
+            return Err(anyhow::anyhow!("Schedule {:?} is empty", s));
+        }
+
+        Ok(ScheduleWindow::Weekly { days, start_minute, end_minute })
+    }
+}
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const ALL_WEEKDAYS: [Weekday; 7] =
+    [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun];
+
+/// `mon-fri`, `sat,sun` or `mon,wed-fri`.
+fn parse_weekdays(s: &str) -> Result<Vec<Weekday>> {
+    let mut days = Vec::new();
+    for part in s.split(',') {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (first.parse::<Weekday>(), last.parse::<Weekday>()),
+            None => (part.parse::<Weekday>(), part.parse::<Weekday>()),
+        };
+        let (Ok(mut day), Ok(last)) = (first, last) else {
+            return Err(anyhow::anyhow!("Unknown weekdays {:?}", part));
+        };
+        // Ranges may wrap around the week, e.g. fri-mon
+        loop {
+            if !days.contains(&day) {
+                days.push(day);
+            }
+            if day == last {
+                break;
+            }
+            day = day.succ();
+        }
+    }
+    Ok(days)
+}
+
+/// `hh:mm` as minutes since midnight, `24:00` is the end of the day.
+fn parse_minute_of_day(s: &str) -> Result<u32> {
+    let (hours, minutes) = s
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("Time {:?} is not hh:mm", s))?;
+    let (hours, minutes) = (hours.trim().parse::<u32>()?, minutes.trim().parse::<u32>()?);
+    if minutes >= 60 || hours * 60 + minutes > MINUTES_PER_DAY {
+        return Err(anyhow::anyhow!("Time {:?} is not within a day", s));
+    }
+    Ok(hours * 60 + minutes)
+}
+
+/// Which monitors the capture loop should grab on every tick.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum MonitorSelection {
+    #[default]
+    Primary,
+    All,
+    /// Monitor ids or names, e.g. `["1", "DP-2"]`
+    List(Vec<String>),
+}
+
+impl MonitorSelection {
+    pub fn matches(&self, id: u32, name: &str, is_primary: bool) -> bool {
+        match self {
+            MonitorSelection::Primary => is_primary,
+            MonitorSelection::All => true,
+            MonitorSelection::List(monitors) => monitors
+                .iter()
+                .any(|m| m == name || m.parse::<u32>().is_ok_and(|m| m == id)),
+        }
+    }
+}
+
+impl From<&str> for MonitorSelection {
+    fn from(s: &str) -> Self {
+        match s.trim().to_lowercase().as_str() {
+            "all" => MonitorSelection::All,
+            "primary" | "" => MonitorSelection::Primary,
+            _ => MonitorSelection::List(
+                s.split(',')
+                    .map(|m| m.trim().to_string())
+                    .filter(|m| !m.is_empty())
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<String> for MonitorSelection {
+    fn from(s: String) -> Self {
+        MonitorSelection::from(s.as_str())
+    }
+}
+
+/// Selects application windows by app name and/or title, both given as regular expressions.
+///
+/// A window has to match every pattern that is set; with no pattern set every window matches.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct WindowSelector {
+    pub app_name: Option<String>,
+    pub title: Option<String>,
+}
+
+impl WindowSelector {
+    pub fn new(app_name: Option<String>, title: Option<String>) -> Self {
+        Self { app_name, title }
+    }
+
+    /// Compiles the patterns, failing on an invalid regular expression.
+    pub fn compile(&self) -> Result<WindowMatcher> {
+        let compile = |pattern: &Option<String>| -> Result<Option<Regex>> {
+            pattern
+                .as_deref()
+                .map(|p| Regex::new(p).map_err(|e| anyhow::anyhow!("Invalid window pattern {}: {}", p, e)))
+                .transpose()
+        };
+
+        Ok(WindowMatcher {
+            app_name: compile(&self.app_name)?,
+            title: compile(&self.title)?,
+        })
+    }
+}
+
+/// Compiled form of a [`WindowSelector`].
+#[derive(Debug, Clone)]
+pub struct WindowMatcher {
+    app_name: Option<Regex>,
+    title: Option<Regex>,
+}
+
+impl WindowMatcher {
+    pub fn matches(&self, app_name: &str, title: &str) -> bool {
+        self.app_name.as_ref().is_none_or(|r| r.is_match(app_name))
+            && self.title.as_ref().is_none_or(|r| r.is_match(title))
+    }
+}
+
+/// What the capture loop records: whole monitors (see `monitors`), individual windows,
+/// or one of the display-less sources used for replaying recordings and testing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum CaptureTarget {
+    #[default]
+    Monitors,
+    Windows(WindowSelector),
+    /// Images of a directory, replayed in file name order
+    ImageDirectory(String),
+    /// Frames of an existing MP4 recording
+    Video(String),
+    /// Generated frames of the given size
+    Synthetic { width: u32, height: u32 },
+}
+
+/// Position and size of a monitor in the virtual screen, with its HiDPI scale factor.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MonitorInfo {
+    pub id: u32,
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+}
+
+/// Rectangle of a [`CaptureRegion`], either in pixels or as fractions (0-1) of the frame size.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum RegionRect {
+    Pixels { x: u32, y: u32, width: u32, height: u32 },
+    Normalized { x: f32, y: f32, width: f32, height: f32 },
+}
+
+/// Part of the screen to capture instead of the whole frame, every region forms its own stream.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CaptureRegion {
+    pub name: Option<String>,
+    pub rect: RegionRect,
+}
+
+impl CaptureRegion {
+    pub fn pixels(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self { name: None, rect: RegionRect::Pixels { x, y, width, height } }
+    }
+
+    pub fn normalized(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self { name: None, rect: RegionRect::Normalized { x, y, width, height } }
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Pixel rectangle of the region in a frame of the given size, clamped to the frame.
+    /// `None` if the region lies outside of the frame.
+    pub fn resolve(&self, index: usize, frame_width: u32, frame_height: u32) -> Option<RegionInfo> {
+        let (x, y, width, height) = match self.rect {
+            RegionRect::Pixels { x, y, width, height } => (x, y, width, height),
+            RegionRect::Normalized { x, y, width, height } => {
+                let scale = |value: f32, size: u32| (value.clamp(0.0, 1.0) * size as f32).round() as u32;
+                (
+                    scale(x, frame_width),
+                    scale(y, frame_height),
+                    scale(width, frame_width),
+                    scale(height, frame_height),
+                )
+            }
+        };
+        let width = width.min(frame_width.saturating_sub(x));
+        let height = height.min(frame_height.saturating_sub(y));
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        Some(RegionInfo {
+            index,
+            name: self.name.clone(),
+            x,
+            y,
+            width,
+            height,
+            frame_width,
+            frame_height,
+        })
+    }
+}
+
+/// Parses `x,y,width,height` with an optional `name:` prefix. Values with a decimal point are
+/// fractions of the frame size, e.g. `0.5,0,0.5,1` for the right half, others are pixels.
+impl std::str::FromStr for CaptureRegion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, rect) = match s.split_once(':') {
+            Some((name, rect)) => (Some(name.trim()), rect),
+            None => (None, s),
+        };
+        let values: Vec<&str> = rect.split(',').map(str::trim).collect();
+        if values.len() != 4 {
+            return Err(anyhow::anyhow!("Region {:?} is not x,y,width,height", s));
+        }
+
+        // Fractions like 0.5, or values that are all 0 or 1 like 0,0,1,1 for the whole screen
+        let is_normalized = values.iter().any(|value| value.contains('.'))
+            || values.iter().all(|value| matches!(*value, "0" | "1"));
+        let region = if is_normalized {
+            let v = values.iter().map(|value| value.parse::<f32>()).collect::<Result<Vec<_>, _>>()?;
+            CaptureRegion::normalized(v[0], v[1], v[2], v[3])
+        } else {
+            let v = values.iter().map(|value| value.parse::<u32>()).collect::<Result<Vec<_>, _>>()?;
+            CaptureRegion::pixels(v[0], v[1], v[2], v[3])
+        };
+        Ok(match name {
+            Some(name) if !name.is_empty() => region.with_name(name),
+            _ => region,
+        })
+    }
+}
+
+/// Pixel rectangle a frame was cropped to, within the full frame it was cut from.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RegionInfo {
+    /// Position of the region in [`ScreenCaptureConfig::regions`]
+    pub index: usize,
+    pub name: Option<String>,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub frame_width: u32,
+    pub frame_height: u32,
+}
+
+impl RegionInfo {
+    /// Maps a point given as fractions of the region to fractions of the full frame.
+    pub fn to_frame_coordinates(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            (self.x as f32 + x * self.width as f32) / self.frame_width as f32,
+            (self.y as f32 + y * self.height as f32) / self.frame_height as f32,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub app_name: String,
+}
+
+/// A frame coming out of the capture loop, tagged with the monitor or window it was taken from.
+///
+/// Frame numbers are counted per stream, every monitor and every window forms its own stream.
+#[derive(Debug, Clone)]
+pub struct CapturedFrame {
+    pub frame_number: u64,
+    pub monitor_id: Option<u32>,
+    pub monitor: Option<MonitorInfo>,
+    pub window: Option<WindowInfo>,
+    /// Region of the monitor or window the frame was cropped to
+    pub region: Option<RegionInfo>,
+    /// Wall-clock time the frame was on screen, kept through recordings and their processing
+    pub captured_at: DateTime<Local>,
+    pub image: DynamicImage,
+}
+
+impl CapturedFrame {
+    pub fn new(frame_number: u64, image: DynamicImage) -> Self {
+        Self {
+            frame_number,
+            monitor_id: None,
+            monitor: None,
+            window: None,
+            region: None,
+            captured_at: Local::now(),
+            image,
+        }
+    }
+
+    pub fn with_captured_at(mut self, captured_at: DateTime<Local>) -> Self {
+        self.captured_at = captured_at;
+        self
+    }
+
+    pub fn with_monitor_id(mut self, monitor_id: u32) -> Self {
+        self.monitor_id = Some(monitor_id);
+        self
+    }
+
+    pub fn with_monitor(mut self, monitor: MonitorInfo) -> Self {
+        self.monitor_id = Some(monitor.id);
+        self.monitor = Some(monitor);
+        self
+    }
+
+    pub fn with_window(mut self, window: WindowInfo) -> Self {
+        self.window = Some(window);
+        self
+    }
+
+    pub fn with_region(mut self, region: RegionInfo) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Cuts `region` out of the frame, `None` if it lies outside of the frame.
+    pub fn crop(&self, index: usize, region: &CaptureRegion) -> Option<CapturedFrame> {
+        let info = region.resolve(index, self.image.width(), self.image.height())?;
+        let image = self.image.crop_imm(info.x, info.y, info.width, info.height);
+        Some(Self {
+            frame_number: self.frame_number,
+            monitor_id: self.monitor_id,
+            monitor: self.monitor.clone(),
+            window: self.window.clone(),
+            region: Some(info),
+            captured_at: self.captured_at,
+            image,
+        })
+    }
+
+    /// Name of the stream the frame belongs to, e.g. `monitor2`, `window1234` or `monitor2-region0`.
+    pub fn stream_name(&self) -> Option<String> {
+        let source = match (&self.window, self.monitor_id) {
+            (Some(window), _) => Some(format!("window{}", window.id)),
+            (None, Some(monitor_id)) => Some(format!("monitor{}", monitor_id)),
+            (None, None) => None,
+        };
+        match (source, &self.region) {
+            (Some(source), Some(region)) => Some(format!("{}-region{}", source, region.index)),
+            (None, Some(region)) => Some(format!("region{}", region.index)),
+            (source, None) => source,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScreenCaptureConfig {
     pub fps: Option<f32>,
+    /// Seconds between two captures, takes precedence over `fps`
+    pub interval: Option<f32>,
+    /// Stop after this many captures, also for a live capture
+    pub count: Option<u64>,
     pub duration: Option<u64>,
     pub save_screenshot_to: Option<String>,
     pub save_video_to: Option<String>,
     pub video_chunk_duration: Option<u64>,
     pub quality: Option<u8>,
+    pub monitors: Option<MonitorSelection>,
+    pub target: Option<CaptureTarget>,
+    pub encoder: Option<EncoderSettings>,
+    /// Write videos as fragmented MP4 with one fragment per this many frames, `None` writes plain MP4.
+    pub fragment_frames: Option<u32>,
+    /// Applied to `save_screenshot_to` and `save_video_to` while capturing
+    pub retention: Option<RetentionPolicy>,
+    pub screenshot_format: Option<ScreenshotFormat>,
+    /// Compression quality (1-100) of lossy screenshot formats, `quality` is the scaling factor
+    pub compression_quality: Option<u8>,
+    pub resize_filter: Option<ResizeFilter>,
+    /// Capture at a rate between these bounds depending on screen activity instead of at `fps`
+    pub adaptive_fps: Option<AdaptiveFps>,
+    /// Crop every monitor or window to these regions, `None` keeps the whole frame
+    pub regions: Option<Vec<CaptureRegion>>,
+    /// Applied to every frame right after capturing, before cropping to `regions`
+    pub redaction: Option<RedactionConfig>,
+    /// Frames every consumer of the pipeline may fall behind before its backpressure policy applies
+    pub frame_queue_capacity: Option<usize>,
+    /// Backpressure of recording and saving, blocking by default so no frame is lost
+    pub recorder_backpressure: Option<BackpressurePolicy>,
+    /// Payload encoding of frames written to stdout, JPEG uses `compression_quality`
+    pub stdout_encoding: Option<FrameEncoding>,
+    pub stdout_format: Option<StdoutFormat>,
+    /// Only capture within these time windows, `None` captures around the clock
+    pub schedule: Option<CaptureSchedule>,
 }
 
 impl Default for ScreenCaptureConfig {
     fn default() -> Self {
         Self {
             fps: None,
+            interval: None,
+            count: None,
             duration: None,
             save_screenshot_to: None,
             save_video_to: None,
             video_chunk_duration: None,
             quality: Some(QUALITY_DEFAULT),
+            monitors: None,
+            target: None,
+            encoder: None,
+            fragment_frames: None,
+            retention: None,
+            screenshot_format: None,
+            compression_quality: None,
+            resize_filter: None,
+            adaptive_fps: None,
+            regions: None,
+            redaction: None,
+            frame_queue_capacity: None,
+            recorder_backpressure: None,
+            stdout_encoding: None,
+            stdout_format: None,
+            schedule: None,
         }
     }
 }
@@ -49,8 +959,34 @@ impl ScreenCaptureConfig {
         config
     }
 
+    /// Capture rate, may be below 1 fps, e.g. 0.1 for a frame every 10 seconds.
     pub fn get_fps(&self) -> f32 {
-        self.fps.unwrap_or(FPS_DEFAULT)
+        match self.interval.filter(|interval| *interval > 0.0) {
+            Some(interval) => 1.0 / interval,
+            None => self.fps.filter(|fps| *fps > 0.0).unwrap_or(FPS_DEFAULT),
+        }
+    }
+
+    pub fn get_interval(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.get_fps())
+    }
+
+    pub fn get_count(&self) -> Option<u64> {
+        self.count
+    }
+
+    /// Number of captures of a fixed rate capture, `None` captures until stopped.
+    pub fn get_frames_to_capture(&self) -> Option<u64> {
+        let frames_in_duration = match self.get_duration() {
+            0 => None,
+            // A capture at the very end of the duration doesn't count, the tolerance keeps
+            // float rates like 0.3 fps from adding one
+            duration => Some(((duration as f64 * self.get_fps() as f64) - 1e-3).ceil().max(1.0) as u64),
+        };
+        match (frames_in_duration, self.get_count()) {
+            (Some(frames), Some(count)) => Some(frames.min(count)),
+            (frames, count) => frames.or(count),
+        }
     }
 
     pub fn get_duration(&self) -> u64 {
@@ -72,4 +1008,69 @@ impl ScreenCaptureConfig {
     pub fn get_quality(&self) -> u8 {
         self.quality.unwrap_or(QUALITY_DEFAULT)
     }
+
+    pub fn get_monitors(&self) -> MonitorSelection {
+        self.monitors.clone().unwrap_or_default()
+    }
+
+    pub fn get_target(&self) -> CaptureTarget {
+        self.target.clone().unwrap_or_default()
+    }
+
+    pub fn get_encoder(&self) -> EncoderSettings {
+        self.encoder.clone().unwrap_or_default()
+    }
+
+    pub fn get_fragment_frames(&self) -> Option<u32> {
+        self.fragment_frames
+    }
+
+    pub fn get_retention(&self) -> Option<RetentionPolicy> {
+        self.retention.clone().filter(|retention| !retention.is_unlimited())
+    }
+
+    pub fn get_screenshot_format(&self) -> ScreenshotFormat {
+        self.screenshot_format.unwrap_or_default()
+    }
+
+    pub fn get_compression_quality(&self) -> u8 {
+        self.compression_quality.unwrap_or(COMPRESSION_QUALITY_DEFAULT).clamp(1, 100)
+    }
+
+    pub fn get_resize_filter(&self) -> ResizeFilter {
+        self.resize_filter.unwrap_or_default()
+    }
+
+    pub fn get_adaptive_fps(&self) -> Option<AdaptiveFps> {
+        self.adaptive_fps.clone()
+    }
+
+    pub fn get_regions(&self) -> Vec<CaptureRegion> {
+        self.regions.clone().unwrap_or_default()
+    }
+
+    pub fn get_redaction(&self) -> Option<RedactionConfig> {
+        self.redaction.clone().filter(|redaction| !redaction.is_empty())
+    }
+
+    pub fn get_frame_queue_capacity(&self) -> usize {
+        self.frame_queue_capacity.unwrap_or(FRAME_QUEUE_CAPACITY_DEFAULT).max(1)
+    }
+
+    pub fn get_recorder_backpressure(&self) -> BackpressurePolicy {
+        self.recorder_backpressure.unwrap_or_default()
+    }
+
+    pub fn get_stdout_encoding(&self) -> FrameEncoding {
+        self.stdout_encoding.unwrap_or_default()
+    }
+
+    pub fn get_stdout_format(&self) -> StdoutFormat {
+        self.stdout_format.unwrap_or_default()
+    }
+
+    /// Schedule with at least one window, `None` captures around the clock.
+    pub fn get_schedule(&self) -> Option<CaptureSchedule> {
+        self.schedule.clone().filter(|schedule| !schedule.get_windows().is_empty())
+    }
 }
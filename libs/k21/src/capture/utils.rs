@@ -1,21 +1,59 @@
 use anyhow::Result;
-use image::DynamicImage;
+use image::{DynamicImage, GenericImageView};
 
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::io::{self, AsyncWriteExt};
-use tokio::sync::broadcast::channel;
+use tokio::io;
 
-use crate::capture::screen_record;
-use super::screen_record::get_screenshot;
+use crate::capture::{mp4_writer, screen_record};
+use crate::image_utils::scale_image;
+use super::source::{create_frame_source, FrameSource};
 use tokio::sync::watch;
-use super::ScreenCaptureConfig;
-use chrono::Local;
+use super::adaptive::AdaptiveRate;
+use super::fanout::{FrameFanout, FrameReceiver, SharedFrame};
+use super::protocol::FrameEncoder;
+use super::y4m::Y4mEncoder;
+use super::redact::Redactor;
+use super::schedule::Schedule;
+use super::handle::{CaptureControl, CaptureHandle};
+use super::manifest::file_sha256_hex;
+use super::retention::{enforce_retention, RetentionReport};
+use super::{
+    CaptureRegion, ChunkManifest, CapturedFrame, FrameTime, MonitorInfo, RegionInfo, ScreenCaptureConfig,
+    ScreenshotFormat, StdoutFormat, WindowInfo,
+};
+use chrono::{Local, Utc};
+
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60);
+const SCHEDULE_RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// A stream without frames for this many capture intervals, at least [`STREAM_IDLE_TIMEOUT_MIN`],
+/// is gone, e.g. a closed window or an unplugged monitor
+const STREAM_IDLE_FRAMES: u32 = 5;
+const STREAM_IDLE_TIMEOUT_MIN: Duration = Duration::from_secs(10);
+
+/// Consumer that records, saves and prints frames, see [`handle_captured_frames`]
+pub const RECORDER_CONSUMER: &str = "recorder";
 
 pub async fn capture(config: ScreenCaptureConfig) -> Result<()> {
     capture_with_stdout(config, false).await
 }
 
-pub async fn capture_with_stdout(mut config: ScreenCaptureConfig, stdout: bool) -> Result<()> {
+pub async fn capture_with_stdout(config: ScreenCaptureConfig, stdout: bool) -> Result<()> {
+    start_capture_with_stdout(config, stdout).await?.wait().await
+}
+
+/// Starts capturing in the background, the returned handle pauses, resumes and stops it.
+pub async fn start_capture(config: ScreenCaptureConfig) -> Result<CaptureHandle> {
+    start_capture_with_stdout(config, false).await
+}
+
+pub async fn start_capture_with_stdout(mut config: ScreenCaptureConfig, stdout: bool) -> Result<CaptureHandle> {
+    if let Some(adaptive_fps) = config.get_adaptive_fps() {
+        adaptive_fps.validate()?;
+    }
+
     if config.get_save_video_to().is_some() {
         config.save_video_to = Some(match &config.get_save_video_to() {
             Some(path) => path.to_string(),
@@ -25,64 +63,252 @@ pub async fn capture_with_stdout(mut config: ScreenCaptureConfig, stdout: bool)
 
     log::info!("Starting capture at {} fps", config.get_fps());
 
-    let (screenshot_tx, mut screenshot_rx) = channel(512);
-    let (close_tx, close_rx) = watch::channel(false);
+    Ok(CaptureHandle::new(|control| {
+        tokio::task::spawn(async move {
+            // Before this run writes chunks of its own
+            if let Some(output_dir) = config.get_save_video_to() {
+                if let Err(e) = tokio::task::spawn_blocking(move || recover_video_chunks(&output_dir)).await {
+                    log::error!("Failed to recover video chunks: {}", e);
+                }
+            }
+
+            let mut fanout = FrameFanout::new();
+            let recorder_rx = fanout.subscribe(
+                RECORDER_CONSUMER,
+                config.get_recorder_backpressure(),
+                config.get_frame_queue_capacity(),
+            );
+            let (close_tx, close_rx) = watch::channel(false);
+            let stats = fanout.stats();
+            control.track_fanout(stats.clone());
 
-    let screenshot_task = spawn_screenshot_task(
-        &config,
-        screenshot_tx,
-        close_tx,
-    );
+            let retention_task = spawn_retention_task(&config, close_rx);
+            let outside_schedule = control.outside_schedule();
 
-    let _ = handle_captured_frames(
-        &config,
-        stdout,
-        &mut screenshot_rx,
-        close_rx,
-    ).await;
+            let screenshot_task = match create_frame_source(&config) {
+                Ok(source) => spawn_capture_loop(&config, source, fanout, close_tx, control),
+                Err(e) => tokio::task::spawn(async move {
+                    log::error!("Failed to set up capture: {}", e);
+                    drop(fanout);
+                    let _ = close_tx.send(true);
+                }),
+            };
 
-    log::info!("Exiting...");
-    let _ = screenshot_task.await;
+            if let Err(e) = handle_captured_frames(&config, stdout, recorder_rx, outside_schedule).await {
+                log::error!("Failed to record frames: {}", e);
+            }
 
-    Ok(())
+            log::info!("Exiting...");
+            let _ = screenshot_task.await;
+            stats.log_summary();
+            if let Some(retention_task) = retention_task {
+                let _ = retention_task.await;
+            }
+        })
+    }))
 }
 
+/// Captures the configured target into `fanout`, it is closed once capturing ends.
+///
+/// `outside_schedule` turns `true` while the capture waits for its schedule, pass its
+/// receiver to [`handle_captured_frames`] to close the video chunks then.
 pub fn spawn_screenshot_task(
     config: &ScreenCaptureConfig,
-    screenshot_tx: tokio::sync::broadcast::Sender<(u64, DynamicImage)>,
-    close_tx: tokio::sync::watch::Sender<bool>
+    fanout: FrameFanout,
+    close_tx: tokio::sync::watch::Sender<bool>,
+    outside_schedule: tokio::sync::watch::Sender<bool>
 ) -> tokio::task::JoinHandle<()> {
+    match create_frame_source(config) {
+        Ok(source) => spawn_frame_source_task(config, source, fanout, close_tx, outside_schedule),
+        Err(e) => tokio::task::spawn(async move {
+            log::error!("Failed to set up capture: {}", e);
+            drop(fanout);
+            let _ = close_tx.send(true);
+        }),
+    }
+}
+
+/// Runs the capture loop on any [`FrameSource`], e.g. to feed the pipeline without a display.
+pub fn spawn_frame_source_task(
+    config: &ScreenCaptureConfig,
+    source: Box<dyn FrameSource>,
+    fanout: FrameFanout,
+    close_tx: tokio::sync::watch::Sender<bool>,
+    outside_schedule: tokio::sync::watch::Sender<bool>
+) -> tokio::task::JoinHandle<()> {
+    spawn_capture_loop(config, source, fanout, close_tx, CaptureControl::detached(outside_schedule))
+}
+
+fn spawn_capture_loop(
+    config: &ScreenCaptureConfig,
+    source: Box<dyn FrameSource>,
+    fanout: FrameFanout,
+    close_tx: tokio::sync::watch::Sender<bool>,
+    mut control: CaptureControl
+) -> tokio::task::JoinHandle<()> {
+    // Without its redactor a capture must not record anything
+    let redactor = match config.get_redaction().map(|redaction| Redactor::new(&redaction)).transpose() {
+        Ok(redactor) => redactor.map(Arc::new),
+        Err(e) => return tokio::task::spawn(async move {
+            log::error!("Failed to set up redaction: {}", e);
+            drop(fanout);
+            let _ = close_tx.send(true);
+        }),
+    };
+    let schedule = match config.get_schedule().map(|schedule| Schedule::new(&schedule)).transpose() {
+        Ok(schedule) => schedule,
+        Err(e) => return tokio::task::spawn(async move {
+            log::error!("Failed to set up capture schedule: {}", e);
+            drop(fanout);
+            let _ = close_tx.send(true);
+        }),
+    };
+
     tokio::task::spawn({
-        let interval = Duration::from_secs_f32(1.0 / config.get_fps());
-        let total_frames_to_process = config.get_duration() * config.get_fps() as u64;
+        let mut interval = config.get_interval();
+        let frames_to_capture = config.get_frames_to_capture();
+        let count = config.get_count();
+        let total_duration = Duration::from_secs(config.get_duration());
         let live_capture = config.get_duration() == 0;
         let quality = config.get_quality();
+        let resize_filter = config.get_resize_filter().into();
+        let adaptive_rate = config.get_adaptive_fps().map(AdaptiveRate::new);
+        let regions = Arc::new(config.get_regions());
         async move {
+            let mut source = source;
+            let mut adaptive_rate = adaptive_rate;
             let mut frame_counter: u64 = 1;
-            while live_capture || frame_counter <= total_frames_to_process {
+            let capture_started = Instant::now();
+            let is_adaptive = adaptive_rate.is_some();
+            // The frame count of an adaptive capture isn't known up front, it runs for the duration instead
+            let remaining = |frame_counter: u64| {
+                if is_adaptive {
+                    count.is_none_or(|count| frame_counter <= count)
+                        && (live_capture || capture_started.elapsed() < total_duration)
+                } else {
+                    frames_to_capture.is_none_or(|frames| frame_counter <= frames)
+                }
+            };
+            while remaining(frame_counter) {
+                if !control.wait_while_paused().await {
+                    log::info!("Capture stopped");
+                    break;
+                }
+                if let Some(schedule) = &schedule {
+                    let now = Utc::now();
+                    if !schedule.is_active(now) {
+                        let next_change = schedule.next_change(now);
+                        if control.set_outside_schedule(true) {
+                            match next_change {
+                                Some(next_change) => log::info!(
+                                    "Outside of the capture schedule, capturing again at {}",
+                                    next_change.with_timezone(&Local)
+                                ),
+                                None => log::warn!("The capture schedule doesn't allow capturing within the next week"),
+                            }
+                        }
+                        // Checked again every now and then, the clock may jump e.g. after a suspend
+                        let wait = next_change
+                            .and_then(|next_change| (next_change - now).to_std().ok())
+                            .map_or(SCHEDULE_RECHECK_INTERVAL, |wait| wait.min(SCHEDULE_RECHECK_INTERVAL));
+                        tokio::select! {
+                            _ = tokio::time::sleep(wait) => continue,
+                            _ = control.stopped() => {
+                                log::info!("Capture stopped");
+                                break;
+                            }
+                        }
+                    }
+                    if control.set_outside_schedule(false) {
+                        log::info!("Capture schedule started, capturing again");
+                    }
+                }
                 let capture_start = Instant::now();
-                match get_screenshot(quality).await {
-                    Ok(image) => {
-                        // Use try_send to avoid blocking if receiver is slow
-                        if let Err(e) = screenshot_tx.send((frame_counter, image)) {
+
+                // Sources block (screen grabs, file reads, decoding), so pull from a blocking thread
+                let regions = regions.clone();
+                let redactor = redactor.clone();
+                let pulled = tokio::task::spawn_blocking(move || {
+                    let frames = source.next_frames(frame_counter).map(|frames| {
+                        frames.map(|frames| {
+                            let frames = match &redactor {
+                                Some(redactor) => redact_frames(frames, redactor),
+                                None => frames,
+                            };
+                            crop_to_regions(frames, &regions)
+                                .into_iter()
+                                .map(|mut frame| {
+                                    frame.image = scale_image(frame.image, quality, resize_filter);
+                                    frame
+                                })
+                                .collect::<Vec<_>>()
+                        })
+                    });
+                    let changed = match (&mut adaptive_rate, &frames) {
+                        (Some(adaptive_rate), Ok(Some(frames))) => adaptive_rate.detect_change(frames),
+                        _ => false,
+                    };
+                    (source, adaptive_rate, frames, changed)
+                })
+                .await;
+
+                let (captured, changed) = match pulled {
+                    Ok((returned, returned_rate, captured, changed)) => {
+                        source = returned;
+                        adaptive_rate = returned_rate;
+                        (captured, changed)
+                    }
+                    Err(e) => {
+                        log::error!("Frame source failed: {}", e);
+                        break;
+                    }
+                };
+
+                match captured {
+                    Ok(Some(frames)) => {
+                        control.add_frames(frames.len());
+                        // One frame per stream, all sharing the same frame number
+                        let mut sent = Ok(());
+                        for frame in frames {
+                            sent = fanout.send(frame).await;
+                            if sent.is_err() {
+                                break;
+                            }
+                        }
+                        if let Err(e) = sent {
                             log::error!("Failed to send screenshot: {}", e);
                             break;
                         }
                     },
+                    Ok(None) => {
+                        log::info!("Frame source exhausted");
+                        break;
+                    },
                     Err(e) => {
                         log::error!("Failed to capture screenshot: {}", e);
+                        control.add_error();
                         // Continue to next iteration instead of breaking
-                        tokio::time::sleep(interval).await;
-                        continue;
+                        tokio::select! {
+                            _ = tokio::time::sleep(interval) => continue,
+                            _ = control.stopped() => break,
+                        }
                     }
                 }
                 
                 let capture_duration = capture_start.elapsed();
                 frame_counter += 1;
 
+                if let Some(adaptive_rate) = &mut adaptive_rate {
+                    interval = adaptive_rate.next_interval(changed);
+                    log::debug!("Adaptive capture, changed: {}, next frame in {:?}", changed, interval);
+                }
+
                 if let Some(diff) = interval.checked_sub(capture_duration) {
                     log::debug!("Sleeping for {:?}", diff);
-                    tokio::time::sleep(diff).await;
+                    tokio::select! {
+                        _ = tokio::time::sleep(diff) => {}
+                        _ = control.stopped() => {}
+                    }
                 } else {
                     log::warn!(
                         "Capture took longer than expected: {:?}, will not sleep",
@@ -90,98 +316,353 @@ pub fn spawn_screenshot_task(
                     );
                 }
             }
+            // Consumers finish the frames still queued, then see the end of the capture
+            drop(fanout);
             let _ = close_tx.send(true);
             log::debug!("Screenshot task completed after {} frames", frame_counter - 1);
         }
     })
 }
 
+/// Applies the retention policy to the output directories right away and then periodically,
+/// until capturing stops.
+fn spawn_retention_task(
+    config: &ScreenCaptureConfig,
+    mut close_rx: watch::Receiver<bool>
+) -> Option<tokio::task::JoinHandle<()>> {
+    let policy = config.get_retention()?;
+    let mut dirs: Vec<String> = [config.get_save_video_to(), config.get_save_screenshot_to()]
+        .into_iter()
+        .flatten()
+        .collect();
+    dirs.dedup();
+    if dirs.is_empty() {
+        return None;
+    }
+
+    Some(tokio::task::spawn(async move {
+        loop {
+            for dir in &dirs {
+                let (dir, policy) = (dir.clone(), policy.clone());
+                let enforced = tokio::task::spawn_blocking(move || {
+                    enforce_retention(Path::new(&dir), &policy).map(|report| (dir, report))
+                })
+                .await;
+
+                match enforced {
+                    Ok(Ok((dir, report))) if report != RetentionReport::default() => {
+                        log::info!("Retention in {}: {:?}", dir, report);
+                    }
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => log::error!("Failed to enforce retention: {}", e),
+                    Err(e) => log::error!("Retention task failed: {}", e),
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(RETENTION_INTERVAL) => {}
+                _ = close_rx.changed() => break,
+            }
+        }
+    }))
+}
+
+/// Redacts every frame, frames that can't be redacted are dropped rather than kept unredacted.
+fn redact_frames(frames: Vec<CapturedFrame>, redactor: &Redactor) -> Vec<CapturedFrame> {
+    frames
+        .into_iter()
+        .filter_map(|mut frame| match redactor.redact(&mut frame) {
+            Ok(_) => Some(frame),
+            Err(e) => {
+                log::error!("Dropping frame {} of stream {:?}, redaction failed: {}", frame.frame_number, frame.stream_name(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Replaces every frame by one frame per region, frames are kept whole without regions.
+fn crop_to_regions(frames: Vec<CapturedFrame>, regions: &[CaptureRegion]) -> Vec<CapturedFrame> {
+    if regions.is_empty() {
+        return frames;
+    }
+    frames
+        .iter()
+        .flat_map(|frame| {
+            regions.iter().enumerate().filter_map(move |(index, region)| {
+                let cropped = frame.crop(index, region);
+                if cropped.is_none() {
+                    log::warn!("Region {} lies outside of stream {:?}", index, frame.stream_name());
+                }
+                cropped
+            })
+        })
+        .collect()
+}
+
+/// Video recorder and chunk bookkeeping for a single monitor or window stream.
+struct StreamRecorder {
+    screen_record: screen_record::ScreenCapturer,
+    chunk_number: u64,
+    frames: Vec<FrameTime>,
+    dropped_frames: Vec<u64>,
+    last_frame_number: Option<u64>,
+    dimensions: Option<(u32, u32)>,
+    monitor: Option<MonitorInfo>,
+    window: Option<WindowInfo>,
+    region: Option<RegionInfo>,
+    last_frame_at: Instant,
+}
+
+impl StreamRecorder {
+    fn new(config: &ScreenCaptureConfig) -> Self {
+        Self {
+            screen_record: screen_record::ScreenCapturer::new(
+                config.get_encoder(),
+                config.get_fps(),
+                config.get_fragment_frames(),
+            ),
+            chunk_number: 0,
+            frames: Vec::new(),
+            dropped_frames: Vec::new(),
+            last_frame_number: None,
+            dimensions: None,
+            monitor: None,
+            window: None,
+            region: None,
+            last_frame_at: Instant::now(),
+        }
+    }
+
+    fn record(&mut self, frame: &CapturedFrame, stream_name: Option<&str>, output_dir_video: &str) {
+        if !self.screen_record.is_recording() {
+            let path = video_chunk_path(output_dir_video, stream_name, self.chunk_number);
+            self.screen_record.start(&path);
+        }
+
+        // Frame numbers count capture ticks, so a gap means the stream missed frames
+        if let Some(last_frame_number) = self.last_frame_number {
+            self.dropped_frames.extend(last_frame_number + 1..frame.frame_number);
+        }
+        self.last_frame_number = Some(frame.frame_number);
+        self.last_frame_at = Instant::now();
+
+        match self.screen_record.frame(&frame.image, frame.captured_at) {
+            Ok(()) => {
+                self.frames.push(FrameTime {
+                    frame_number: frame.frame_number,
+                    captured_at: frame.captured_at,
+                });
+                self.dimensions.get_or_insert(frame.image.dimensions());
+                if self.monitor.is_none() {
+                    self.monitor = frame.monitor.clone();
+                }
+                if self.window.is_none() {
+                    self.window = frame.window.clone();
+                }
+                if self.region.is_none() {
+                    self.region = frame.region.clone();
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to record frame {}: {}", frame.frame_number, e);
+                self.dropped_frames.push(frame.frame_number);
+            }
+        }
+    }
+
+    /// Whether the chunk covers `chunk_duration`, counting the time until the next frame of a fixed rate.
+    fn is_chunk_complete(&self, chunk_duration: Duration, frame_interval: Option<Duration>) -> bool {
+        let (Some(first), Some(last)) = (self.frames.first(), self.frames.last()) else {
+            return false;
+        };
+        let captured = (last.captured_at - first.captured_at).to_std().unwrap_or_default();
+        // Frame numbers count capture ticks, so the nominal time doesn't suffer from timer jitter
+        let nominal = frame_interval
+            .map(|interval| interval.mul_f64((last.frame_number - first.frame_number + 1) as f64))
+            .unwrap_or_default();
+        captured.max(nominal) >= chunk_duration
+    }
+
+    fn manifest(&self, config: &ScreenCaptureConfig, stream_name: Option<&str>, video_path: &Path) -> Result<ChunkManifest> {
+        let (width, height) = self.dimensions.unwrap_or_default();
+        let (coded_width, coded_height) = config.get_encoder().coded_size(width, height);
+        Ok(ChunkManifest {
+            video: video_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            sha256: file_sha256_hex(video_path)?,
+            stream: stream_name.map(str::to_string),
+            chunk_number: self.chunk_number,
+            width,
+            height,
+            coded_width: Some(coded_width),
+            coded_height: Some(coded_height),
+            monitor: self.monitor.clone(),
+            window: self.window.clone(),
+            region: self.region.clone(),
+            config: config.clone(),
+            encoder: config.get_encoder(),
+            frames: self.frames.clone(),
+            dropped_frames: self.dropped_frames.clone(),
+        })
+    }
+}
+
+/// Records, saves and/or prints the frames of `frames_rx` until the capture ends, and closes
+/// the video chunks once `outside_schedule` turns `true`.
 pub async fn handle_captured_frames(
     config: &ScreenCaptureConfig,
     stdout: bool,
-    screenshot_rx: &mut tokio::sync::broadcast::Receiver<(u64, DynamicImage)>,
-    close_rx: tokio::sync::watch::Receiver<bool>
+    frames_rx: FrameReceiver,
+    outside_schedule: watch::Receiver<bool>,
 ) -> Result<()> {
-    let screen_record = &mut screen_record::ScreenCapturer::new();
-    let mut chunk_number = 0;
+    let mut recorders: HashMap<Option<String>, StreamRecorder> = HashMap::new();
 
     // Handle frames
     save_or_send_captured_frames(
         config,
         stdout,
-        screen_record,
-        screenshot_rx,
-        close_rx,
-        &mut chunk_number,
+        &mut recorders,
+        frames_rx,
+        outside_schedule,
     ).await;
 
-    // Save final video chunk if needed
-    if config.get_save_video_to().is_some() && !screen_record.is_buf_empty() {
-        save_video_chunk(
-            screen_record, 
-            &mut chunk_number, 
-            config.get_fps(), 
-            config.get_save_video_to().as_ref().unwrap()
-        );
-    }
-    
+    // Save final video chunk of every stream if needed
+    save_open_chunks(config, &mut recorders);
     Ok(())
 }
 
+fn save_open_chunks(config: &ScreenCaptureConfig, recorders: &mut HashMap<Option<String>, StreamRecorder>) {
+    if let Some(output_dir) = config.get_save_video_to() {
+        for (stream_name, recorder) in recorders.iter_mut() {
+            if recorder.screen_record.is_recording() {
+                save_video_chunk(
+                    recorder,
+                    config,
+                    stream_name.as_deref(),
+                    &output_dir
+                );
+            }
+        }
+    }
+}
+
+/// Saves the chunks of streams without frames for `idle_timeout` and drops their recorders.
+fn remove_idle_recorders(
+    config: &ScreenCaptureConfig,
+    recorders: &mut HashMap<Option<String>, StreamRecorder>,
+    idle_timeout: Duration,
+) {
+    recorders.retain(|stream_name, recorder| {
+        if recorder.last_frame_at.elapsed() < idle_timeout {
+            return true;
+        }
+        log::info!("Stream {:?} stopped producing frames, closing its recorder", stream_name);
+        if let Some(output_dir) = config.get_save_video_to() {
+            if recorder.screen_record.is_recording() {
+                save_video_chunk(recorder, config, stream_name.as_deref(), &output_dir);
+            }
+        }
+        false
+    });
+}
+
 async fn save_or_send_captured_frames(
     config: &ScreenCaptureConfig,
     stdout: bool,
-    screen_record: &mut screen_record::ScreenCapturer,
-    screenshot_rx: &mut tokio::sync::broadcast::Receiver<(u64, DynamicImage)>,
-    mut close_rx: tokio::sync::watch::Receiver<bool>,
-    chunk_number: &mut u64,
+    recorders: &mut HashMap<Option<String>, StreamRecorder>,
+    mut frames_rx: FrameReceiver,
+    mut outside_schedule: watch::Receiver<bool>,
 ) {
-    let total_fps_in_chunk = config
-        .get_video_chunk_duration()
-        .map(|duration| config.get_fps() as u64 * duration)
-        .unwrap_or(u64::MAX);  // If no chunk duration specified, use max value to prevent chunks
+    let mut stdout_writer = stdout.then(|| StdoutWriter::new(config));
+    let chunk_duration = config.get_video_chunk_duration().map(Duration::from_secs);
+    // Adaptive captures have no fixed rate, their chunks only go by capture time
+    let frame_interval = config.get_adaptive_fps().is_none().then(|| config.get_interval());
+    let slowest_interval = match config.get_adaptive_fps() {
+        Some(adaptive) => Duration::from_secs_f32(1.0 / adaptive.get_min_fps().max(f32::EPSILON)),
+        None => config.get_interval(),
+    };
+    let idle_timeout = (slowest_interval * STREAM_IDLE_FRAMES).max(STREAM_IDLE_TIMEOUT_MIN);
+    // Sweeps the recorders even when no stream produces frames anymore
+    let mut idle_check = tokio::time::interval(idle_timeout);
+    idle_check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
     loop {
-        tokio::select! {
-            Ok((frame_number, image)) = screenshot_rx.recv() => {
-                if stdout {
-                    send_frame_to_stdout(frame_number, &image).await;
+        let frame = tokio::select! {
+            // Frames captured before the schedule ended are queued already and go into the closing chunks
+            biased;
+            frame = frames_rx.recv() => match frame {
+                Some(frame) => frame,
+                None => break,
+            },
+            Ok(()) = outside_schedule.changed() => {
+                if *outside_schedule.borrow_and_update() {
+                    log::info!("Capture schedule ended, closing video chunks");
+                    save_open_chunks(config, recorders);
                 }
+                continue;
+            }
+            _ = idle_check.tick() => {
+                remove_idle_recorders(config, recorders, idle_timeout);
+                continue;
+            }
+        };
+        let frame_number = frame.frame_number;
+        let stream_name = frame.stream_name();
 
-                // record the frame
-                if config.get_save_video_to().is_some() {
-                    screen_record.frame(&image);
-                    log::info!("frame {}", frame_number);
+        if let Some(writer) = stdout_writer.as_mut() {
+            writer.write_frame(&frame).await;
+        }
 
-                    if frame_number % total_fps_in_chunk == 0 {
-                        log::info!(
-                            "frame {}, total_fps_in_chunk {}",
-                            frame_number,
-                            total_fps_in_chunk
-                        );
-                        save_video_chunk(screen_record, chunk_number, config.get_fps(), config.get_save_video_to().as_ref().unwrap());
-                    }
+        // record the frame into the video of its stream
+        if let Some(output_dir) = config.get_save_video_to() {
+            let recorder = recorders
+                .entry(stream_name.clone())
+                .or_insert_with(|| StreamRecorder::new(config));
+            // A chunk has a single size, e.g. a monitor plugged in or switched to another DPI starts a new one
+            if let Some(frame_size) = recorder.screen_record.frame_size() {
+                if frame_size != frame.image.dimensions() {
+                    log::info!(
+                        "Stream {:?} changed from {}x{} to {}x{}, starting a new chunk",
+                        stream_name,
+                        frame_size.0,
+                        frame_size.1,
+                        frame.image.width(),
+                        frame.image.height()
+                    );
+                    save_video_chunk(recorder, config, stream_name.as_deref(), &output_dir);
                 }
+            }
+            recorder.record(&frame, stream_name.as_deref(), &output_dir);
+            log::info!("frame {} of stream {:?}", frame_number, stream_name);
 
-                // save screenshot to disk
-                if config.get_save_screenshot_to().is_some() {
-                    if let Some(output_dir) = &config.get_save_screenshot_to() {
-                        save_screenshot(frame_number, image.clone(), output_dir);
-                    } else {
-                        log::warn!("Screenshot saving enabled but no output directory specified");
-                    }
-                }
+            if chunk_duration.is_some_and(|duration| recorder.is_chunk_complete(duration, frame_interval)) {
+                log::info!("frame {} completes chunk {}", frame_number, recorder.chunk_number);
+                save_video_chunk(
+                    recorder,
+                    config,
+                    stream_name.as_deref(),
+                    &output_dir
+                );
             }
 
-            Ok(_) = close_rx.changed() => {
-                if *close_rx.borrow() {
-                    log::debug!("Screenshot channel closed, stopping OCR processing");
-                    break;
-                }
+            // Streams of closed windows or unplugged monitors don't send the frame that completes their chunk
+            remove_idle_recorders(config, recorders, idle_timeout);
+        }
+
+        // save screenshot to disk
+        if config.get_save_screenshot_to().is_some() {
+            if let Some(output_dir) = &config.get_save_screenshot_to() {
+                save_screenshot(config, frame_number, stream_name.as_deref(), frame.clone(), output_dir);
+            } else {
+                log::warn!("Screenshot saving enabled but no output directory specified");
             }
         }
     }
-    
+    log::debug!("Capture ended, all frames handled");
+
     if config.get_save_screenshot_to().is_some() {
         if let Some(output_dir) = &config.get_save_screenshot_to() {
             log::info!("Total screenshots saved in directory: {}", 
@@ -190,42 +671,180 @@ async fn save_or_send_captured_frames(
     }
 }
 
-async fn send_frame_to_stdout(frame_number: u64, image: &DynamicImage) {
-    let rgb = image.to_rgb8();
-    let data = rgb.as_raw();
-    let mut stdout = io::stdout();
+/// Writes frames to stdout in the configured [`StdoutFormat`].
+enum StdoutWriter {
+    K21(FrameEncoder<io::Stdout>),
+    /// Y4M carries a single stream, the first stream seen
+    Y4m(Y4mEncoder<io::Stdout>, Option<Option<String>>),
+    /// Y4M stream that ended because its frame size changed
+    Ended,
+}
 
-    log::info!("Sending frame {}, len {}", frame_number, data.len());
+impl StdoutWriter {
+    fn new(config: &ScreenCaptureConfig) -> Self {
+        match config.get_stdout_format() {
+            StdoutFormat::K21 => StdoutWriter::K21(
+                FrameEncoder::new(io::stdout(), config.get_stdout_encoding())
+                    .with_compression_quality(config.get_compression_quality()),
+            ),
+            StdoutFormat::Y4m => StdoutWriter::Y4m(Y4mEncoder::new(io::stdout(), config.get_fps()), None),
+        }
+    }
 
-    // send frame & size of raw image data
-    stdout.write_all(&frame_number.to_le_bytes()).await.unwrap(); // Send frame number
-    stdout.write_all(&rgb.width().to_le_bytes()).await.unwrap(); // Send width
-    stdout.write_all(&rgb.height().to_le_bytes()).await.unwrap(); // Send height
-    stdout.write_all(&data.len().to_le_bytes()).await.unwrap(); // Send data size
-    stdout.write_all(&data).await.unwrap(); // Send frame data
-    stdout.flush().await.unwrap(); // Ensure it's sent
+    async fn write_frame(&mut self, frame: &SharedFrame) {
+        let stream_name = frame.stream_name();
+        let result = match self {
+            StdoutWriter::K21(encoder) => encoder.write_shared_frame(frame.clone()).await,
+            StdoutWriter::Y4m(encoder, stream) => match stream {
+                Some(stream) if *stream != stream_name => return,
+                Some(_) => match encoder.frame_size() {
+                    // A Y4M stream has a single size, ending it beats failing on every frame
+                    Some(size) if size != frame.image.dimensions() => {
+                        log::error!(
+                            "Stream {:?} changed from {}x{} to {}x{}, a Y4M stream can't change its size, ending stdout output",
+                            stream_name,
+                            size.0,
+                            size.1,
+                            frame.image.width(),
+                            frame.image.height()
+                        );
+                        *self = StdoutWriter::Ended;
+                        return;
+                    }
+                    _ => encoder.write_frame(frame).await,
+                },
+                None => {
+                    log::info!("Writing stream {:?} as Y4M, other streams are not sent to stdout", stream_name);
+                    *stream = Some(stream_name.clone());
+                    encoder.write_frame(frame).await
+                }
+            },
+            StdoutWriter::Ended => return,
+        };
+
+        match result {
+            Ok(()) => log::info!("Sent frame {} of stream {:?}", frame.frame_number, stream_name),
+            Err(e) => log::error!("Failed to send frame {} to stdout: {}", frame.frame_number, e),
+        }
+    }
 }
 
-fn save_video_chunk(screen_record: &mut screen_record::ScreenCapturer, chunk_number: &mut u64, fps: f32, output_dir_video: &str) {
+/// File name prefix for a stream, e.g. `output` or `output-monitor2` when the stream is known.
+fn stream_file_prefix(kind: &str, stream_name: Option<&str>) -> String {
+    match stream_name {
+        Some(stream_name) => format!("{}-{}", kind, stream_name),
+        None => kind.to_string(),
+    }
+}
+
+/// Finalizes chunks left unfinished by a previous run, e.g. when it was killed mid-chunk.
+/// Chunks another running capture is writing to the same directory are skipped.
+fn recover_video_chunks(output_dir_video: &str) {
+    let Ok(entries) = std::fs::read_dir(output_dir_video) else {
+        return;
+    };
+
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        if path.extension().is_some_and(|ext| ext == mp4_writer::PART_EXTENSION) {
+            if let Err(e) = mp4_writer::recover_chunk(&path) {
+                log::error!("Failed to recover {}: {}", path.display(), e);
+            }
+        }
+    }
+}
 
+/// Chunk files are named after the time recording of the chunk started.
+fn video_chunk_path(output_dir_video: &str, stream_name: Option<&str>, chunk_number: u64) -> std::path::PathBuf {
     let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let path = std::path::PathBuf::from(output_dir_video)
-            .join(format!("output-{}-{}.mp4", timestamp, chunk_number));
-    
-    screen_record.save(&path, fps);
-    *chunk_number += 1;
+    std::path::PathBuf::from(output_dir_video)
+        .join(format!("{}-{}-{}.mp4", stream_file_prefix("output", stream_name), timestamp, chunk_number))
 }
 
-fn save_screenshot(frame_number: u64, image: DynamicImage, output_dir: &str) {
+fn save_video_chunk(
+    recorder: &mut StreamRecorder,
+    config: &ScreenCaptureConfig,
+    stream_name: Option<&str>,
+    output_dir_video: &str
+) {
+    match recorder.screen_record.save() {
+        Ok(Some(path)) => {
+            let written = recorder
+                .manifest(config, stream_name, &path)
+                .and_then(|manifest| manifest.write(&path));
+            if let Err(e) = written {
+                log::error!("Failed to write manifest of {}: {}", path.display(), e);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => log::error!(
+            "Failed to save video chunk {} of stream {:?} in {}: {}",
+            recorder.chunk_number,
+            stream_name,
+            output_dir_video,
+            e
+        ),
+    }
+
+    recorder.chunk_number += 1;
+    recorder.frames.clear();
+    recorder.dropped_frames.clear();
+    recorder.dimensions = None;
+    recorder.monitor = None;
+    recorder.window = None;
+    recorder.region = None;
+}
+
+fn save_screenshot(
+    config: &ScreenCaptureConfig,
+    frame_number: u64,
+    stream_name: Option<&str>,
+    frame: SharedFrame,
+    output_dir: &str
+) {
+    let format = config.get_screenshot_format();
+    let compression_quality = config.get_compression_quality();
 
     let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let path = std::path::PathBuf::from(output_dir)
-        .join(format!("screenshot-{}-{}.png", timestamp, frame_number));
+    let path = std::path::PathBuf::from(output_dir).join(format!(
+        "{}-{}-{}.{}",
+        stream_file_prefix("screenshot", stream_name),
+        timestamp,
+        frame_number,
+        format.extension()
+    ));
 
-    tokio::task::spawn(async move {
-        match image.save_with_format(&path, image::ImageFormat::Png) {
+    tokio::task::spawn_blocking(move || {
+        let saved = encode_screenshot(&frame.image, format, compression_quality)
+            .and_then(|bytes| std::fs::write(&path, bytes).map_err(anyhow::Error::from));
+        match saved {
             Ok(_) => log::info!("Saved screenshot to {}", path.display()),
             Err(e) => log::error!("Failed to save screenshot: {}", e),
         }
     });
-}
\ No newline at end of file
+}
+
+/// Encodes a screenshot, `compression_quality` (1-100) applies to the lossy formats only.
+fn encode_screenshot(image: &DynamicImage, format: ScreenshotFormat, compression_quality: u8) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    match format {
+        ScreenshotFormat::Png => {
+            image.write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)?;
+        }
+        ScreenshotFormat::Jpeg => {
+            // JPEG has no alpha channel
+            let rgb = DynamicImage::ImageRgb8(image.to_rgb8());
+            let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, compression_quality);
+            rgb.write_with_encoder(encoder)?;
+        }
+        ScreenshotFormat::WebP | ScreenshotFormat::WebPLossless => {
+            let rgba = image.to_rgba8();
+            let encoder = webp::Encoder::from_rgba(rgba.as_raw(), rgba.width(), rgba.height());
+            let encoded = match format {
+                ScreenshotFormat::WebPLossless => encoder.encode_lossless(),
+                _ => encoder.encode(compression_quality as f32),
+            };
+            bytes.extend_from_slice(&encoded);
+        }
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,342 @@
+use std::io::ErrorKind;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Local};
+use image::{DynamicImage, RgbImage};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::CapturedFrame;
+
+const Y4M_MAGIC: &str = "YUV4MPEG2";
+const FRAME_TAG: &str = "FRAME";
+const MAX_HEADER_LEN: usize = 4096;
+
+/// Chroma subsampling of YUV frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChromaSampling {
+    C420,
+    C422,
+    C444,
+    /// Luma only
+    Mono,
+}
+
+impl ChromaSampling {
+    /// Width and height of each chroma plane, `None` for monochrome frames.
+    fn chroma_size(self, width: u32, height: u32) -> Option<(u32, u32)> {
+        match self {
+            ChromaSampling::C420 => Some((width.div_ceil(2), height.div_ceil(2))),
+            ChromaSampling::C422 => Some((width.div_ceil(2), height)),
+            ChromaSampling::C444 => Some((width, height)),
+            ChromaSampling::Mono => None,
+        }
+    }
+
+    /// Bytes of a frame in planar layout.
+    pub fn frame_len(self, width: u32, height: u32) -> usize {
+        let luma = width as usize * height as usize;
+        match self.chroma_size(width, height) {
+            Some((chroma_width, chroma_height)) => luma + 2 * chroma_width as usize * chroma_height as usize,
+            None => luma,
+        }
+    }
+}
+
+/// Converts a planar YUV frame with BT.601 colors to RGB.
+pub(crate) fn yuv_to_rgb(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    chroma: ChromaSampling,
+    full_range: bool,
+) -> Result<RgbImage> {
+    if data.len() < chroma.frame_len(width, height) {
+        bail!("YUV frame of {}x{} needs {} bytes, got {}", width, height, chroma.frame_len(width, height), data.len());
+    }
+
+    let luma_len = width as usize * height as usize;
+    let (y_plane, chroma_planes) = data.split_at(luma_len);
+    let chroma_size = chroma.chroma_size(width, height);
+    let chroma_len = chroma_size.map_or(0, |(w, h)| w as usize * h as usize);
+    let (u_plane, v_plane) = chroma_planes.split_at(chroma_len);
+
+    Ok(RgbImage::from_fn(width, height, |x, y| {
+        let luma = y_plane[(y * width + x) as usize] as f32;
+        let (u, v) = match chroma_size {
+            Some((chroma_width, chroma_height)) => {
+                let chroma_x = x * chroma_width / width;
+                let chroma_y = y * chroma_height / height;
+                let index = (chroma_y * chroma_width + chroma_x) as usize;
+                (u_plane[index] as f32 - 128.0, v_plane[index] as f32 - 128.0)
+            }
+            None => (0.0, 0.0),
+        };
+
+        let (r, g, b) = if full_range {
+            (luma + 1.402 * v, luma - 0.344136 * u - 0.714136 * v, luma + 1.772 * u)
+        } else {
+            let luma = 1.164 * (luma - 16.0);
+            (luma + 1.596 * v, luma - 0.392 * u - 0.813 * v, luma + 2.017 * u)
+        };
+        image::Rgb([r.round().clamp(0.0, 255.0) as u8, g.round().clamp(0.0, 255.0) as u8, b.round().clamp(0.0, 255.0) as u8])
+    }))
+}
+
+/// Converts an RGB image to planar limited range YUV 4:2:0 with BT.601 colors.
+fn rgb_to_yuv420(rgb: &RgbImage) -> Vec<u8> {
+    let (width, height) = rgb.dimensions();
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut data = Vec::with_capacity(ChromaSampling::C420.frame_len(width, height));
+
+    for pixel in rgb.pixels() {
+        let [r, g, b] = pixel.0.map(|c| c as f32);
+        data.push((16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0).round() as u8);
+    }
+
+    let mut u_plane = Vec::with_capacity((chroma_width * chroma_height) as usize);
+    let mut v_plane = Vec::with_capacity((chroma_width * chroma_height) as usize);
+    for chroma_y in 0..chroma_height {
+        for chroma_x in 0..chroma_width {
+            // Average of the 2x2 block, fewer pixels at odd edges
+            let (mut r, mut g, mut b, mut pixels) = (0.0, 0.0, 0.0, 0.0);
+            for y in chroma_y * 2..(chroma_y * 2 + 2).min(height) {
+                for x in chroma_x * 2..(chroma_x * 2 + 2).min(width) {
+                    let [pr, pg, pb] = rgb.get_pixel(x, y).0;
+                    r += pr as f32;
+                    g += pg as f32;
+                    b += pb as f32;
+                    pixels += 1.0;
+                }
+            }
+            let (r, g, b) = (r / pixels, g / pixels, b / pixels);
+            u_plane.push((128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0).round().clamp(0.0, 255.0) as u8);
+            v_plane.push((128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0).round().clamp(0.0, 255.0) as u8);
+        }
+    }
+
+    data.extend_from_slice(&u_plane);
+    data.extend_from_slice(&v_plane);
+    data
+}
+
+/// Frame rate as the fraction Y4M headers use, e.g. `30:1` or `1:10`.
+fn frame_rate_fraction(fps: f32) -> (u32, u32) {
+    let (mut numerator, mut denominator) = if fps >= 1.0 && fps.fract() == 0.0 {
+        (fps as u32, 1)
+    } else {
+        ((fps * 1000.0).round().max(1.0) as u32, 1000)
+    };
+    let gcd = {
+        let (mut a, mut b) = (numerator, denominator);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
+    numerator /= gcd;
+    denominator /= gcd;
+    (numerator, denominator)
+}
+
+/// Writes frames as a YUV4MPEG2 stream, e.g. for `ffmpeg -f yuv4mpegpipe -i -`.
+///
+/// Frames are converted to limited range 4:2:0, all frames of a stream must have the size of the first one.
+pub struct Y4mEncoder<W> {
+    writer: W,
+    fps: f32,
+    size: Option<(u32, u32)>,
+}
+
+impl<W: AsyncWrite + Unpin> Y4mEncoder<W> {
+    pub fn new(writer: W, fps: f32) -> Self {
+        Self { writer, fps, size: None }
+    }
+
+    /// Size of the frames of the stream, `None` before the first frame.
+    pub fn frame_size(&self) -> Option<(u32, u32)> {
+        self.size
+    }
+
+    pub async fn write_frame(&mut self, frame: &CapturedFrame) -> Result<()> {
+        let size = (frame.image.width(), frame.image.height());
+        match self.size {
+            None => {
+                let (numerator, denominator) = frame_rate_fraction(self.fps);
+                let header = format!(
+                    "{} W{} H{} F{}:{} Ip A1:1 C420jpeg XCOLORRANGE=LIMITED\n",
+                    Y4M_MAGIC, size.0, size.1, numerator, denominator
+                );
+                self.writer.write_all(header.as_bytes()).await?;
+                self.size = Some(size);
+            }
+            Some(stream_size) if stream_size != size => {
+                bail!(
+                    "Frame {} is {}x{}, the Y4M stream is {}x{}",
+                    frame.frame_number,
+                    size.0,
+                    size.1,
+                    stream_size.0,
+                    stream_size.1
+                );
+            }
+            Some(_) => {}
+        }
+
+        let data = rgb_to_yuv420(&frame.image.to_rgb8());
+        self.writer.write_all(FRAME_TAG.as_bytes()).await?;
+        self.writer.write_all(b"\n").await?;
+        self.writer.write_all(&data).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Parameters of a Y4M stream header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Y4mHeader {
+    pub width: u32,
+    pub height: u32,
+    /// Frames per second, `None` if the header has no usable frame rate
+    pub fps: Option<f64>,
+    pub chroma: ChromaSampling,
+    pub full_range: bool,
+}
+
+impl Y4mHeader {
+    fn parse(line: &str) -> Result<Self> {
+        let mut tokens = line.split_ascii_whitespace();
+        if tokens.next() != Some(Y4M_MAGIC) {
+            bail!("Not a Y4M stream, it doesn't start with {}", Y4M_MAGIC);
+        }
+
+        let (mut width, mut height, mut fps) = (None, None, None);
+        let mut chroma = ChromaSampling::C420;
+        let mut full_range = false;
+        for token in tokens {
+            let (tag, value) = token.split_at(1);
+            match tag {
+                "W" => width = Some(value.parse::<u32>()?),
+                "H" => height = Some(value.parse::<u32>()?),
+                "F" => {
+                    fps = value.split_once(':').and_then(|(numerator, denominator)| {
+                        let numerator = numerator.parse::<f64>().ok()?;
+                        let denominator = denominator.parse::<f64>().ok()?;
+                        (numerator > 0.0 && denominator > 0.0).then(|| numerator / denominator)
+                    })
+                }
+                "C" => {
+                    chroma = match value {
+                        "420" | "420jpeg" | "420paldv" | "420mpeg2" => ChromaSampling::C420,
+                        "422" => ChromaSampling::C422,
+                        "444" => ChromaSampling::C444,
+                        "mono" => ChromaSampling::Mono,
+                        _ => bail!("Unsupported Y4M colorspace {}, only 8 bit 420, 422, 444 and mono are", value),
+                    }
+                }
+                "X" if value.eq_ignore_ascii_case("COLORRANGE=FULL") => full_range = true,
+                _ => {}
+            }
+        }
+
+        match (width, height) {
+            (Some(width), Some(height)) if width > 0 && height > 0 => Ok(Self { width, height, fps, chroma, full_range }),
+            _ => Err(anyhow!("Y4M header without frame size")),
+        }
+    }
+}
+
+/// Reads frames of a YUV4MPEG2 stream, e.g. from `ffmpeg -i input.mkv -f yuv4mpegpipe -`.
+pub struct Y4mDecoder<R> {
+    reader: R,
+    header: Option<Y4mHeader>,
+    started_at: DateTime<Local>,
+    frames_read: u64,
+}
+
+impl<R: AsyncRead + Unpin> Y4mDecoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            header: None,
+            started_at: Local::now(),
+            frames_read: 0,
+        }
+    }
+
+    /// Stream header, `None` until the first frame was read.
+    pub fn header(&self) -> Option<&Y4mHeader> {
+        self.header.as_ref()
+    }
+
+    /// Next frame, `None` at the end of the stream.
+    ///
+    /// Frames are numbered from 1 and timed from when the stream started by its frame rate.
+    pub async fn read_frame(&mut self) -> Result<Option<CapturedFrame>> {
+        let header = match self.header.clone() {
+            Some(header) => header,
+            None => {
+                let Some(line) = self.read_line().await? else {
+                    return Ok(None);
+                };
+                let header = Y4mHeader::parse(&line)?;
+                log::info!("Reading Y4M stream of {}x{} at {:?} fps", header.width, header.height, header.fps);
+                self.started_at = Local::now();
+                self.header = Some(header.clone());
+                header
+            }
+        };
+
+        let Some(line) = self.read_line().await? else {
+            return Ok(None);
+        };
+        if !line.starts_with(FRAME_TAG) {
+            bail!("Expected a Y4M frame, got {:?}", line);
+        }
+
+        let mut data = vec![0u8; header.chroma.frame_len(header.width, header.height)];
+        match self.reader.read_exact(&mut data).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                log::warn!("Y4M stream ended within frame {}", self.frames_read + 1);
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        let image = yuv_to_rgb(&data, header.width, header.height, header.chroma, header.full_range)?;
+        let captured_at = match header.fps {
+            Some(fps) => self.started_at + ChronoDuration::microseconds((self.frames_read as f64 * 1_000_000.0 / fps) as i64),
+            None => Local::now(),
+        };
+        self.frames_read += 1;
+
+        Ok(Some(
+            CapturedFrame::new(self.frames_read, DynamicImage::ImageRgb8(image)).with_captured_at(captured_at),
+        ))
+    }
+
+    /// Reads a header line without its newline, `None` at the end of the stream.
+    async fn read_line(&mut self) -> Result<Option<String>> {
+        let mut line = Vec::new();
+        loop {
+            let mut byte = [0u8; 1];
+            match self.reader.read_exact(&mut byte).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof && line.is_empty() => return Ok(None),
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => bail!("Y4M stream ended within a header"),
+                Err(e) => return Err(e.into()),
+            }
+            if byte[0] == b'\n' {
+                return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+            }
+            if line.len() >= MAX_HEADER_LEN {
+                bail!("Y4M header line is longer than {} bytes", MAX_HEADER_LEN);
+            }
+            line.push(byte[0]);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 mod utils;
 pub(crate) use utils::get_current_timestamp_str;
+pub(crate) use utils::TIMESTAMP_FORMAT;
 pub use utils::get_results_from_state;
 pub(crate) use utils::decode_base64;
 
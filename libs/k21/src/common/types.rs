@@ -1,5 +1,8 @@
 use serde::{Serialize, Deserialize};
 
+use crate::capture::RegionInfo;
+use crate::image2text::OcrResult;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ProcessingType {
     Vision,
@@ -37,11 +40,39 @@ pub struct ImageData {
     frame_number: u64,
     content: String,
     processing_type: ProcessingType,
+    monitor_id: Option<u32>,
+    window_title: Option<String>,
+    app_name: Option<String>,
+    region: Option<RegionInfo>,
+    /// Words and lines behind `content`, for OCR
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ocr: Option<OcrResult>,
 }
 
 impl ImageData {
     pub fn new(timestamp: String, frame_number: u64, content: String, processing_type: ProcessingType) -> Self {
-        Self { timestamp, frame_number, content, processing_type }
+        Self { timestamp, frame_number, content, processing_type, monitor_id: None, window_title: None, app_name: None, region: None, ocr: None }
+    }
+
+    pub fn with_monitor_id(mut self, monitor_id: Option<u32>) -> Self {
+        self.monitor_id = monitor_id;
+        self
+    }
+
+    pub fn with_window(mut self, window_title: Option<String>, app_name: Option<String>) -> Self {
+        self.window_title = window_title;
+        self.app_name = app_name;
+        self
+    }
+
+    pub fn with_region(mut self, region: Option<RegionInfo>) -> Self {
+        self.region = region;
+        self
+    }
+
+    pub fn with_ocr(mut self, ocr: Option<OcrResult>) -> Self {
+        self.ocr = ocr;
+        self
     }
 
     pub fn timestamp(&self) -> &str {
@@ -59,6 +90,33 @@ impl ImageData {
     pub fn processing_type(&self) -> &ProcessingType {
         &self.processing_type
     }
+
+    pub fn monitor_id(&self) -> Option<u32> {
+        self.monitor_id
+    }
+
+    pub fn window_title(&self) -> Option<&str> {
+        self.window_title.as_deref()
+    }
+
+    pub fn app_name(&self) -> Option<&str> {
+        self.app_name.as_deref()
+    }
+
+    /// Region of the screen the content was read from, `None` for the whole monitor or window.
+    pub fn region(&self) -> Option<&RegionInfo> {
+        self.region.as_ref()
+    }
+
+    /// Structured OCR result the content was rendered from, `None` for vision.
+    pub fn ocr(&self) -> Option<&OcrResult> {
+        self.ocr.as_ref()
+    }
+
+    /// Aggregate OCR confidence (0-1) of the frame, see [`OcrResult::confidence`].
+    pub fn confidence(&self) -> Option<f32> {
+        self.ocr.as_ref().and_then(|ocr| ocr.confidence)
+    }
 }
 
 pub type ImageDataCollection = Vec<ImageData>;
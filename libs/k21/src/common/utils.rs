@@ -2,8 +2,10 @@ use std::sync::{Arc, Mutex};
 use anyhow::Result;
 use base64::{Engine as _, engine::general_purpose::STANDARD};
 
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
 pub fn get_current_timestamp_str() -> String {
-    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+    chrono::Local::now().format(TIMESTAMP_FORMAT).to_string()
 }
 
 pub async fn get_results_from_state<T: Clone>(state: Arc<Mutex<T>>) -> Result<T> {
@@ -1,5 +1,9 @@
 mod ocr;
-pub use ocr::{process_ocr, OcrConfig, OcrModel};
+pub use ocr::{process_ocr, recognize_text, OcrConfig, OcrModel};
+pub use ocr::{register_ocr_engine, OcrEngine, OcrEngineRegistry, TesseractEngine};
+pub use ocr::{MACOS_ENGINE, NATIVE_ENGINE, TESSERACT_ENGINE, WINDOWS_ENGINE};
+pub use ocr::{preprocess, PreprocessConfig, PreprocessStep};
+pub use ocr::{BoundingBox, NormalizedBox, OcrBlock, OcrLine, OcrPage, OcrResult, OcrWord};
 
 mod vision;
 pub use vision::{process_image_vision_from_path, process_image_vision};
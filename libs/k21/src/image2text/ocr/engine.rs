@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use image::DynamicImage;
+
+use super::types::{OcrConfig, OcrModel, OcrResult};
+
+/// Name of the built-in tesseract engine
+pub const TESSERACT_ENGINE: &str = "tesseract";
+/// Name of the built-in Vision engine on macOS
+pub const MACOS_ENGINE: &str = "macos";
+/// Name of the built-in Windows.Media.Ocr engine on Windows
+pub const WINDOWS_ENGINE: &str = "windows";
+
+/// Engine `OcrModel::Native` and `OcrModel::Default` use on this platform.
+pub const NATIVE_ENGINE: &str = if cfg!(target_os = "macos") {
+    MACOS_ENGINE
+} else if cfg!(target_os = "windows") {
+    WINDOWS_ENGINE
+} else {
+    TESSERACT_ENGINE
+};
+
+/// Finds text in images, see [`OcrEngineRegistry`] to make an engine selectable by name.
+///
+/// Boxes of the result are in pixels of the image passed in. Preprocessing and confidence
+/// filtering of the [`OcrConfig`] are applied around the engine, it only has to recognize.
+#[async_trait]
+pub trait OcrEngine: Send + Sync {
+    /// Name the engine is selected by with [`OcrConfig::engine`]
+    fn name(&self) -> &str;
+
+    async fn recognize(&self, image: &DynamicImage, config: &OcrConfig) -> Result<OcrResult>;
+}
+
+/// OCR engines by name, with the built-in engines of this platform registered from the start.
+pub struct OcrEngineRegistry {
+    engines: RwLock<HashMap<String, Arc<dyn OcrEngine>>>,
+}
+
+impl OcrEngineRegistry {
+    /// Registry with only the built-in engines.
+    pub fn new() -> Self {
+        let registry = Self {
+            engines: RwLock::new(HashMap::new()),
+        };
+        registry.register(Arc::new(super::ocr_tesseract::TesseractEngine));
+        #[cfg(target_os = "macos")]
+        registry.register(Arc::new(super::ocr_mac::MacOsEngine));
+        #[cfg(target_os = "windows")]
+        registry.register(Arc::new(super::ocr_win::WindowsEngine));
+        registry
+    }
+
+    /// Registry `process_ocr` and `recognize_text` pick their engine from.
+    pub fn global() -> &'static OcrEngineRegistry {
+        static REGISTRY: OnceLock<OcrEngineRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(OcrEngineRegistry::new)
+    }
+
+    /// Adds `engine` under its name, replacing an engine of the same name.
+    pub fn register(&self, engine: Arc<dyn OcrEngine>) {
+        let name = engine.name().to_lowercase();
+        let mut engines = self.engines.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if engines.insert(name.clone(), engine).is_some() {
+            log::debug!("Replaced OCR engine {}", name);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn OcrEngine>> {
+        let engines = self.engines.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        engines.get(&name.to_lowercase()).cloned()
+    }
+
+    /// Names of the registered engines, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let engines = self.engines.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut names: Vec<String> = engines.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Engine `config` selects, by [`OcrConfig::engine`] or else by its [`OcrModel`].
+    pub fn resolve(&self, config: &OcrConfig) -> Result<Arc<dyn OcrEngine>> {
+        let name = config.get_engine_name();
+        self.get(&name)
+            .ok_or_else(|| anyhow!("Unknown OCR engine {:?}, registered are: {}", name, self.names().join(", ")))
+    }
+}
+
+impl Default for OcrEngineRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Adds `engine` to the global registry, so configs can select it by name.
+pub fn register_ocr_engine(engine: Arc<dyn OcrEngine>) {
+    OcrEngineRegistry::global().register(engine);
+}
+
+impl OcrModel {
+    /// Name of the built-in engine of the model on this platform.
+    pub fn engine_name(&self) -> &'static str {
+        match self {
+            OcrModel::Tesseract => TESSERACT_ENGINE,
+            OcrModel::Native | OcrModel::Default => NATIVE_ENGINE,
+        }
+    }
+}
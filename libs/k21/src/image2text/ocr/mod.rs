@@ -1,43 +1,56 @@
 // OCR module structure
 #[cfg(target_os = "macos")]
 mod ocr_mac;
+#[cfg(target_os = "macos")]
+pub use ocr_mac::MacOsEngine;
 #[cfg(target_os = "windows")]
 mod ocr_win;
+#[cfg(target_os = "windows")]
+pub use ocr_win::WindowsEngine;
 
 mod ocr_tesseract;
+pub use ocr_tesseract::TesseractEngine;
+
+mod engine;
+pub use engine::{register_ocr_engine, OcrEngine, OcrEngineRegistry, MACOS_ENGINE, NATIVE_ENGINE, TESSERACT_ENGINE, WINDOWS_ENGINE};
+
+mod preprocess;
+pub use preprocess::{preprocess, PreprocessConfig, PreprocessStep};
 
 mod types;
-pub use types::{OcrConfig, OcrModel};
+pub use types::{BoundingBox, NormalizedBox, OcrBlock, OcrConfig, OcrLine, OcrModel, OcrPage, OcrResult, OcrWord};
 
 use anyhow::Result;
 use image::DynamicImage;
 
+/// Text of `img`, with line positions if the config asks for bounding boxes.
 pub async fn process_ocr(img: &DynamicImage, config: &OcrConfig) -> Result<String> {
-    match config.ocr_model {
-        OcrModel::Tesseract => {
-            use self::ocr_tesseract::perform_ocr_tesseract;
-            Ok(perform_ocr_tesseract(img, config))
-        },
-        OcrModel::Default | OcrModel::Native => {
-            #[cfg(target_os = "macos")]
-            {
-                use self::ocr_mac::process_ocr_macosx;
-                Ok(process_ocr_macosx(img, config).await)
-            }
-            #[cfg(target_os = "windows")]
-            {
-                use self::ocr_win::process_ocr_windows;
-                process_ocr_windows(img, config).await
-            }
-            #[cfg(target_os = "linux")]
-            {
-                use self::ocr_tesseract::perform_ocr_tesseract;
-                Ok(perform_ocr_tesseract(img, config))
-            }
-            #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
-            {
-                unimplemented!()
+    let result = recognize_text(img, config).await?;
+    Ok(result.to_text(config.get_bounding_boxes()))
+}
+
+/// Words, lines and blocks found in `img` with their boxes and confidence, without the ones
+/// below the configured minimum confidence.
+pub async fn recognize_text(img: &DynamicImage, config: &OcrConfig) -> Result<OcrResult> {
+    let mut result = match &config.preprocess {
+        Some(preprocess_config) => {
+            let (image, preprocess_config) = (img.clone(), preprocess_config.clone());
+            let preprocessed = tokio::task::spawn_blocking(move || preprocess(&image, &preprocess_config)).await??;
+            let result = recognize_text_with_engine(&preprocessed, config).await?;
+            // Boxes refer to the original image, not the upscaled one
+            if (preprocessed.width(), preprocessed.height()) != (img.width(), img.height()) {
+                result.scaled_to(img.width(), img.height())
+            } else {
+                result
             }
         }
-    }
-} 
\ No newline at end of file
+        None => recognize_text_with_engine(img, config).await?,
+    };
+    result.retain_confident(config.get_min_word_confidence(), config.get_min_line_confidence());
+    Ok(result)
+}
+
+async fn recognize_text_with_engine(img: &DynamicImage, config: &OcrConfig) -> Result<OcrResult> {
+    let engine = OcrEngineRegistry::global().resolve(config)?;
+    engine.recognize(img, config).await
+}
@@ -4,9 +4,28 @@ use cidre::{
     ns,
     vn::{self, ImageRequestHandler, RecognizeTextRequest},
 };
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use image::{DynamicImage, GenericImageView};
 use std::{ffi::c_void, ptr::null_mut};
-use super::types::OcrConfig;
+use super::engine::{OcrEngine, MACOS_ENGINE};
+use super::types::{BoundingBox, OcrConfig, OcrLine, OcrResult, OcrWord};
+
+/// Apple's Vision text recognition, the native engine on macOS.
+#[cfg(target_os = "macos")]
+pub struct MacOsEngine;
+
+#[cfg(target_os = "macos")]
+#[async_trait]
+impl OcrEngine for MacOsEngine {
+    fn name(&self) -> &str {
+        MACOS_ENGINE
+    }
+
+    async fn recognize(&self, image: &DynamicImage, config: &OcrConfig) -> Result<OcrResult> {
+        process_ocr_macosx(image, config).await
+    }
+}
 
 
 
@@ -17,15 +36,15 @@ extern "C" fn release_callback(_refcon: *mut c_void, _data_ptr: *const *const c_
 }
 
 #[cfg(target_os = "macos")]
-pub async fn process_ocr_macosx(image: &DynamicImage, config: &OcrConfig) -> String {
+pub async fn process_ocr_macosx(image: &DynamicImage, _config: &OcrConfig) -> Result<OcrResult> {
 
     cidre::objc::ar_pool(|| {
-        let (width, height) = image.dimensions();
+        let (image_width, image_height) = image.dimensions();
         let rgb = image.grayscale().to_luma8();
         let raw_data = rgb.as_raw();
 
-        let width = usize::try_from(width).unwrap();
-        let height = usize::try_from(height).unwrap();
+        let width = image_width as usize;
+        let height = image_height as usize;
 
         let mut pixel_buf_out = None;
 
@@ -44,42 +63,64 @@ pub async fn process_ocr_macosx(image: &DynamicImage, config: &OcrConfig) -> Str
             )
             .to_result_unchecked(pixel_buf_out)
         }
-        .unwrap();
+        .map_err(|e| anyhow!("Failed to create pixel buffer: {:?}", e))?;
 
-        let handler = ImageRequestHandler::with_cv_pixel_buf(&pixel_buf, None).unwrap();
+        let handler = ImageRequestHandler::with_cv_pixel_buf(&pixel_buf, None)
+            .ok_or_else(|| anyhow!("Failed to create Vision request handler"))?;
         let mut request = RecognizeTextRequest::new();
         request.set_uses_lang_correction(false);
         request.set_automatically_detects_lang(true);
         let requests = ns::Array::<vn::Request>::from_slice(&[&request]);
-        let result = handler.perform(&requests);
-
-        if result.is_err() {
-            return "".to_string();
-        }
+        handler.perform(&requests).map_err(|e| anyhow!("Vision text recognition failed: {:?}", e))?;
 
+        let mut lines = Vec::new();
         if let Some(results) = request.results() {
-            if !results.is_empty() {
-                let mut ocr_text: String = String::new();
-                results.iter().for_each(|result| {
-                    let observation_result = result.top_candidates(1).get(0).unwrap();
-                    let text = observation_result.string();
-
-                    if config.bounding_boxes.unwrap_or(OcrConfig::get_default_bounding_boxes()) {
-                        let bounds = result.bounding_box();
-                        // Vision's coordinate system has (0,0) at bottom-left, with y going up
-                        // To get top-left, we use x and (1 - y)
-                        ocr_text.push_str(&format!("({:.2}, {:.2}) ", bounds.origin.x, 1.0 - bounds.origin.y));
+            for result in results.iter() {
+                let candidates = result.top_candidates(1);
+                let Some(candidate) = candidates.iter().next() else {
+                    continue;
+                };
+                let text = candidate.string().to_string();
+                let confidence = Some(candidate.confidence());
+
+                // Vision's coordinate system has (0,0) at bottom-left, with y going up
+                let bounds = result.bounding_box();
+                let left = (bounds.origin.x * image_width as f64).max(0.0) as u32;
+                let top = ((1.0 - bounds.origin.y - bounds.size.height) * image_height as f64).max(0.0) as u32;
+                let line_width = (bounds.size.width * image_width as f64) as u32;
+                let line_height = (bounds.size.height * image_height as f64) as u32;
+
+                // Vision only boxes whole lines, words get a share of it by their length
+                let chars = text.chars().count().max(1) as u32;
+                let mut offset = 0;
+                let mut words = Vec::new();
+                for word in text.split(' ') {
+                    let word_chars = word.chars().count() as u32;
+                    if !word.is_empty() {
+                        words.push(OcrWord {
+                            text: word.to_string(),
+                            bounding_box: BoundingBox::new(
+                                left + line_width * offset / chars,
+                                top,
+                                line_width * word_chars / chars,
+                                line_height,
+                                image_width,
+                                image_height,
+                            ),
+                            confidence,
+                        });
                     }
-                    
-                    ocr_text.push_str(text.to_string().as_str());
-                    ocr_text.push(' ');
-                });
-                
-                let ocr_text = ocr_text.trim_end().to_string();
-                return ocr_text;
+                    offset += word_chars + 1;
+                }
+                if !words.is_empty() {
+                    lines.push(OcrLine {
+                        bounding_box: BoundingBox::new(left, top, line_width, line_height, image_width, image_height),
+                        words,
+                    });
+                }
             }
         }
 
-        String::from("")
+        Ok(OcrResult::from_lines(image_width, image_height, lines))
     })
-}
\ No newline at end of file
+}
@@ -1,49 +1,237 @@
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
 use image::DynamicImage;
-use rusty_tesseract::{Args, DataOutput, Image};
+use rusty_tesseract::{Args, Data};
 use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
 
-use super::types::OcrConfig;
+use super::engine::{OcrEngine, TESSERACT_ENGINE};
+use super::types::{BoundingBox, OcrBlock, OcrConfig, OcrLine, OcrPage, OcrResult, OcrWord};
 
-pub fn perform_ocr_tesseract(
-    image: &DynamicImage,
-    config: &OcrConfig
-) -> String {
-    let language_string = "eng".to_string();
+/// Tesseract's word level in `image_to_data` output
+const WORD_LEVEL: i32 = 5;
 
-    let args = Args {
-        lang: language_string,
-        config_variables: HashMap::from([("tessedit_create_tsv".into(), "1".into())]),
+/// Page, block, paragraph and line number of a word
+type LineKey = (i32, i32, i32, i32);
+
+fn tesseract_args(config: &OcrConfig) -> Args {
+    let mut config_variables = HashMap::from([("tessedit_create_tsv".to_string(), "1".to_string())]);
+    let files = [
+        ("tessedit_char_whitelist", config.char_whitelist.clone()),
+        ("tessedit_char_blacklist", config.char_blacklist.clone()),
+        ("user_words_file", config.user_words.as_ref().map(|path| path.to_string_lossy().to_string())),
+        ("user_patterns_file", config.user_patterns.as_ref().map(|path| path.to_string_lossy().to_string())),
+    ];
+    for (key, value) in files {
+        if let Some(value) = value {
+            config_variables.insert(key.to_string(), value);
+        }
+    }
+    // Explicit variables win over the ones above
+    config_variables.extend(config.config_variables.clone().unwrap_or_default());
+
+    Args {
+        lang: config.get_languages().join("+"),
+        config_variables,
         dpi: Some(config.dpi.unwrap_or(OcrConfig::get_default_dpi()) as i32),
         psm: Some(config.psm.unwrap_or(OcrConfig::get_default_psm()) as i32),
         oem: Some(config.oem.unwrap_or(OcrConfig::get_default_oem()) as i32)
+    }
+}
+
+/// Runs the `tesseract` binary, the engine of [`OcrModel::Tesseract`](super::OcrModel::Tesseract) and of native OCR on Linux.
+pub struct TesseractEngine;
+
+#[async_trait]
+impl OcrEngine for TesseractEngine {
+    fn name(&self) -> &str {
+        TESSERACT_ENGINE
+    }
+
+    async fn recognize(&self, image: &DynamicImage, config: &OcrConfig) -> Result<OcrResult> {
+        perform_ocr_tesseract(image, config)
+    }
+}
+
+/// Whether `config` ends up running tesseract.
+fn uses_tesseract(config: &OcrConfig) -> bool {
+    config.get_engine_name().eq_ignore_ascii_case(TESSERACT_ENGINE)
+}
+
+pub(crate) fn validate_tesseract_config(config: &OcrConfig) -> Result<()> {
+    for (name, path) in [("User words", &config.user_words), ("User patterns", &config.user_patterns)] {
+        if let Some(path) = path {
+            if !path.is_file() {
+                bail!("{} file {} doesn't exist", name, path.display());
+            }
+        }
+    }
+    if let Some(tessdata_path) = &config.tessdata_path {
+        if !tessdata_path.is_dir() {
+            bail!("Tessdata directory {} doesn't exist", tessdata_path.display());
+        }
+    }
+    for key in config.config_variables.iter().flat_map(|variables| variables.keys()) {
+        if key.is_empty() || key.contains(['=', ' ']) {
+            bail!("Invalid tesseract config variable {:?}", key);
+        }
+    }
+
+    let languages = config.get_languages();
+    if let Some(language) = languages.iter().find(|language| {
+        language.is_empty() || !language.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }) {
+        bail!("Invalid tesseract language {:?}", language);
+    }
+    if !uses_tesseract(config) {
+        return Ok(());
+    }
+
+    let missing: Vec<&String> = match &config.tessdata_path {
+        Some(tessdata_path) => languages
+            .iter()
+            .filter(|language| !tessdata_path.join(format!("{}.traineddata", language)).is_file())
+            .collect(),
+        None => {
+            let installed = rusty_tesseract::get_tesseract_langs()
+                .map_err(|e| anyhow!("Failed to list tesseract languages, is tesseract installed? {}", e))?;
+            languages.iter().filter(|language| !installed.contains(language)).collect()
+        }
     };
+    if !missing.is_empty() {
+        let location = config.tessdata_path.as_deref().map_or("the tessdata directory".to_string(), |path| path.display().to_string());
+        bail!(
+            "Missing traineddata for {} in {}",
+            missing.iter().map(|language| format!("{}.traineddata", language)).collect::<Vec<_>>().join(", "),
+            location
+        );
+    }
+
+    Ok(())
+}
 
-    let ocr_image = Image::from_dynamic_image(image).unwrap();
-
-    // Extract data output
-    let data_output = rusty_tesseract::image_to_data(&ocr_image, &args).unwrap();
-    data_output_to_text(&data_output, config.bounding_boxes.unwrap_or(OcrConfig::get_default_bounding_boxes()))
-}
-
-fn data_output_to_text(data_output: &DataOutput, add_bounding_boxes: bool) -> String {
-    let (width, height) = data_output.data.first()
-        .map(|line| (line.width as f32, line.height as f32))
-        .unwrap_or((1.0, 1.0));
-
-    data_output.data.iter()
-        .filter(|line| !line.text.is_empty())
-        .map(|line| {
-            if add_bounding_boxes {
-                // Normalize top-left corner coordinates to 0-1 range
-                let x = line.left as f32 / width;
-                let y = line.top as f32 / height;
-                
-                // Format with coordinates, rounded to 2 decimal places
-                format!("({:.2}, {:.2}) {}", x, y, line.text)
-            } else {
-                line.text.clone()
+/// Words tesseract finds in `image`, grouped into pages, blocks and lines in reading order.
+pub fn perform_ocr_tesseract(image: &DynamicImage, config: &OcrConfig) -> Result<OcrResult> {
+    let args = tesseract_args(config);
+    let data = run_tesseract(image, &args, config.tessdata_path.as_deref())
+        .map_err(|e| anyhow!("Tesseract failed with languages {}: {}", args.lang, e))?;
+    Ok(data_to_result(&data, image.width(), image.height()))
+}
+
+/// Runs the `tesseract` binary on `image` and parses its TSV output.
+///
+/// The tessdata directory goes on the command line of every call, so configs with different
+/// directories can run at the same time.
+fn run_tesseract(image: &DynamicImage, args: &Args, tessdata_path: Option<&Path>) -> Result<Vec<Data>> {
+    let image_file = tempfile::Builder::new().prefix("k21-ocr-").suffix(".png").tempfile()?;
+    image
+        .save_with_format(image_file.path(), image::ImageFormat::Png)
+        .map_err(|e| anyhow!("Failed to prepare image for tesseract: {}", e))?;
+
+    let mut command = Command::new("tesseract");
+    command.arg(image_file.path()).arg("stdout").arg("-l").arg(&args.lang);
+    for (option, value) in [("--dpi", args.dpi), ("--psm", args.psm), ("--oem", args.oem)] {
+        if let Some(value) = value {
+            command.arg(option).arg(value.to_string());
+        }
+    }
+    if let Some(tessdata_path) = tessdata_path {
+        command.arg("--tessdata-dir").arg(tessdata_path);
+    }
+    for (key, value) in &args.config_variables {
+        command.arg("-c").arg(format!("{}={}", key, value));
+    }
+
+    let output = command.output().map_err(|e| match e.kind() {
+        ErrorKind::NotFound => anyhow!("tesseract not found, is it installed and on the PATH?"),
+        _ => anyhow!("Failed to run tesseract: {}", e),
+    })?;
+    if !output.status.success() {
+        bail!("tesseract exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(parse_tsv(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Rows of tesseract's TSV output, the header and malformed rows are skipped.
+fn parse_tsv(tsv: &str) -> Vec<Data> {
+    tsv.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.splitn(12, '\t').collect();
+            if fields.len() < 11 {
+                return None;
             }
+            let number = |index: usize| fields[index].trim().parse::<i32>().ok();
+            Some(Data {
+                level: number(0)?,
+                page_num: number(1)?,
+                block_num: number(2)?,
+                par_num: number(3)?,
+                line_num: number(4)?,
+                word_num: number(5)?,
+                left: number(6)?,
+                top: number(7)?,
+                width: number(8)?,
+                height: number(9)?,
+                conf: fields[10].trim().parse().ok()?,
+                text: fields.get(11).map_or(String::new(), |text| text.to_string()),
+            })
         })
-        .collect::<Vec<String>>()
-        .join(" ")
-}
\ No newline at end of file
+        .collect()
+}
+
+fn data_to_result(rows: &[Data], width: u32, height: u32) -> OcrResult {
+    let bounding_box = |data: &Data| {
+        BoundingBox::new(
+            data.left.max(0) as u32,
+            data.top.max(0) as u32,
+            data.width.max(0) as u32,
+            data.height.max(0) as u32,
+            width,
+            height,
+        )
+    };
+
+    let mut lines: Vec<(LineKey, Vec<OcrWord>)> = Vec::new();
+    for data in rows.iter().filter(|data| data.level == WORD_LEVEL && !data.text.trim().is_empty()) {
+        let key = (data.page_num, data.block_num, data.par_num, data.line_num);
+        let word = OcrWord {
+            text: data.text.trim().to_string(),
+            bounding_box: bounding_box(data),
+            // Tesseract reports 0-100, and -1 for rows without a word
+            confidence: (data.conf >= 0.0).then(|| (data.conf / 100.0).min(1.0)),
+        };
+        match lines.last_mut() {
+            Some((last_key, words)) if *last_key == key => words.push(word),
+            _ => lines.push((key, vec![word])),
+        }
+    }
+
+    let mut result = OcrResult::new(width, height);
+    let mut current = None;
+    for ((page_num, block_num, _, _), words) in lines {
+        let line = OcrLine {
+            bounding_box: BoundingBox::union(words.iter().map(|word| &word.bounding_box), width, height)
+                .unwrap_or_default(),
+            words,
+        };
+        let page_changed = current.map(|(page, _)| page) != Some(page_num);
+        if page_changed {
+            result.pages.push(OcrPage { bounding_box: line.bounding_box, blocks: Vec::new() });
+        }
+        if let Some(page) = result.pages.last_mut() {
+            if page_changed || current != Some((page_num, block_num)) {
+                page.blocks.push(OcrBlock { bounding_box: line.bounding_box, lines: Vec::new() });
+            }
+            if let Some(block) = page.blocks.last_mut() {
+                block.lines.push(line);
+            }
+        }
+        current = Some((page_num, block_num));
+    }
+
+    result.fit_boxes();
+    result
+}
@@ -1,13 +1,31 @@
 use anyhow::Result;
+use async_trait::async_trait;
 use image::DynamicImage;
 
-use super::types::OcrConfig;
+use super::engine::{OcrEngine, WINDOWS_ENGINE};
+use super::types::{BoundingBox, OcrConfig, OcrLine, OcrResult, OcrWord};
 
+/// Windows.Media.Ocr with the languages of the user profile, the native engine on Windows.
+#[cfg(target_os = "windows")]
+pub struct WindowsEngine;
 
 #[cfg(target_os = "windows")]
-pub async fn process_ocr_windows(img: &DynamicImage, config: &OcrConfig) -> Result<String> {
+#[async_trait]
+impl OcrEngine for WindowsEngine {
+    fn name(&self) -> &str {
+        WINDOWS_ENGINE
+    }
+
+    async fn recognize(&self, image: &DynamicImage, config: &OcrConfig) -> Result<OcrResult> {
+        process_ocr_windows(image, config).await
+    }
+}
 
-    log::info!("Processing OCR on Windows, config.boundingboxes: {:?} not in use for bounding boxes", config.bounding_boxes);
+
+#[cfg(target_os = "windows")]
+pub async fn process_ocr_windows(img: &DynamicImage, config: &OcrConfig) -> Result<OcrResult> {
+
+    log::debug!("Processing OCR on Windows with {:?}", config.ocr_model);
 
     use std::io::Cursor;
     use windows::{
@@ -33,5 +51,26 @@ pub async fn process_ocr_windows(img: &DynamicImage, config: &OcrConfig) -> Resu
     let text_engine = OcrEngine::TryCreateFromUserProfileLanguages()?;
     let extracted_text = text_engine.RecognizeAsync(&soft_bitmap)?.get()?;
 
-    Ok(extracted_text.Text()?.to_string())
+
+    let (width, height) = (img.width(), img.height());
+    let to_box = |rect: windows::Foundation::Rect| {
+        BoundingBox::new(rect.X.max(0.0) as u32, rect.Y.max(0.0) as u32, rect.Width as u32, rect.Height as u32, width, height)
+    };
+    let mut lines = Vec::new();
+    for line in extracted_text.Lines()? {
+        let mut words = Vec::new();
+        for word in line.Words()? {
+            words.push(OcrWord {
+                text: word.Text()?.to_string(),
+                bounding_box: to_box(word.BoundingRect()?),
+                // Windows doesn't report a confidence
+                confidence: None,
+            });
+        }
+        if let Some(bounding_box) = BoundingBox::union(words.iter().map(|word| &word.bounding_box), width, height) {
+            lines.push(OcrLine { bounding_box, words });
+        }
+    }
+
+    Ok(OcrResult::from_lines(width, height, lines))
 }
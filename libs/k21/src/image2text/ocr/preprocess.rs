@@ -0,0 +1,260 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{anyhow, Result};
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage, Luma};
+use serde::{Deserialize, Serialize};
+
+/// Upscaling never goes beyond this factor
+const MAX_UPSCALE: f32 = 4.0;
+/// Upscaling stops at images of this many pixels
+const MAX_UPSCALED_PIXELS: f32 = 64_000_000.0;
+/// Share of the darkest and brightest pixels contrast normalization ignores
+const CONTRAST_CLIP: f32 = 0.01;
+/// How much darker than its surroundings a pixel must be to become text when thresholding
+const THRESHOLD_OFFSET: i32 = 10;
+
+/// Numbers the images dumped for debugging, so frames processed at once don't overwrite each other
+static DEBUG_IMAGE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A step of the image preprocessing ahead of OCR.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum PreprocessStep {
+    Grayscale,
+    /// Stretches the brightness so the text uses the full range
+    NormalizeContrast,
+    /// Inverts images that are mostly dark, for dark themes
+    AutoInvert,
+    /// Black text on white, each pixel compared to the mean of its surroundings
+    AdaptiveThreshold,
+    /// Scales the screen's DPI up to the DPI OCR works best at
+    Upscale,
+    /// Median filter against speckles and compression artifacts
+    Denoise,
+}
+
+impl PreprocessStep {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PreprocessStep::Grayscale => "grayscale",
+            PreprocessStep::NormalizeContrast => "contrast",
+            PreprocessStep::AutoInvert => "invert",
+            PreprocessStep::AdaptiveThreshold => "threshold",
+            PreprocessStep::Upscale => "upscale",
+            PreprocessStep::Denoise => "denoise",
+        }
+    }
+}
+
+impl std::str::FromStr for PreprocessStep {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "grayscale" | "gray" => Ok(PreprocessStep::Grayscale),
+            "contrast" | "normalize-contrast" => Ok(PreprocessStep::NormalizeContrast),
+            "invert" | "auto-invert" => Ok(PreprocessStep::AutoInvert),
+            "threshold" | "adaptive-threshold" => Ok(PreprocessStep::AdaptiveThreshold),
+            "upscale" => Ok(PreprocessStep::Upscale),
+            "denoise" => Ok(PreprocessStep::Denoise),
+            _ => Err(anyhow!("Unknown preprocessing step {:?}", s)),
+        }
+    }
+}
+
+/// Image preprocessing that runs before any OCR backend.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PreprocessConfig {
+    /// Steps in the order they run
+    pub steps: Option<Vec<PreprocessStep>>,
+    /// DPI of the captured screen
+    pub source_dpi: Option<u32>,
+    /// DPI `Upscale` scales towards
+    pub target_dpi: Option<u32>,
+    /// Size in pixels of the surroundings `AdaptiveThreshold` compares to
+    pub threshold_window: Option<u32>,
+    /// Directory the image after every step is written to, for tuning
+    pub debug_dir: Option<PathBuf>,
+}
+
+impl PreprocessConfig {
+    pub fn new(steps: Vec<PreprocessStep>) -> Self {
+        Self {
+            steps: Some(steps),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_dpi(mut self, source_dpi: Option<u32>, target_dpi: Option<u32>) -> Self {
+        self.source_dpi = source_dpi;
+        self.target_dpi = target_dpi;
+        self
+    }
+
+    pub fn with_debug_dir(mut self, debug_dir: PathBuf) -> Self {
+        self.debug_dir = Some(debug_dir);
+        self
+    }
+
+    pub fn get_steps(&self) -> Vec<PreprocessStep> {
+        self.steps.clone().unwrap_or_else(|| {
+            vec![
+                PreprocessStep::Grayscale,
+                PreprocessStep::AutoInvert,
+                PreprocessStep::NormalizeContrast,
+                PreprocessStep::Upscale,
+            ]
+        })
+    }
+
+    pub fn get_source_dpi(&self) -> u32 {
+        self.source_dpi.unwrap_or(96).max(1)
+    }
+
+    pub fn get_target_dpi(&self) -> u32 {
+        self.target_dpi.unwrap_or(300)
+    }
+
+    pub fn get_threshold_window(&self) -> u32 {
+        self.threshold_window.unwrap_or(31).max(3)
+    }
+
+    /// Factor `Upscale` scales an image of the given size by.
+    pub fn upscale_factor(&self, width: u32, height: u32) -> f32 {
+        let factor = (self.get_target_dpi() as f32 / self.get_source_dpi() as f32).clamp(1.0, MAX_UPSCALE);
+        let max_factor = (MAX_UPSCALED_PIXELS / (width.max(1) as f32 * height.max(1) as f32)).sqrt();
+        factor.min(max_factor).max(1.0)
+    }
+}
+
+/// Runs the steps of `config` on `image`. Boxes found in the result are in pixels of the
+/// returned image, which is larger than `image` after `Upscale`.
+pub fn preprocess(image: &DynamicImage, config: &PreprocessConfig) -> Result<DynamicImage> {
+    let steps = config.get_steps();
+    let debug_id = DEBUG_IMAGE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    if let Some(debug_dir) = &config.debug_dir {
+        std::fs::create_dir_all(debug_dir)
+            .map_err(|e| anyhow!("Failed to create preprocessing debug directory {}: {}", debug_dir.display(), e))?;
+        dump(debug_dir, debug_id, 0, "input", image);
+    }
+
+    let mut image = image.clone();
+    for (index, step) in steps.iter().enumerate() {
+        image = match step {
+            PreprocessStep::Grayscale => DynamicImage::ImageLuma8(image.to_luma8()),
+            PreprocessStep::NormalizeContrast => DynamicImage::ImageLuma8(normalize_contrast(image.to_luma8())),
+            PreprocessStep::AutoInvert => auto_invert(image),
+            PreprocessStep::AdaptiveThreshold => {
+                DynamicImage::ImageLuma8(adaptive_threshold(&image.to_luma8(), config.get_threshold_window()))
+            }
+            PreprocessStep::Upscale => {
+                let factor = config.upscale_factor(image.width(), image.height());
+                if factor > 1.0 {
+                    let width = (image.width() as f32 * factor).round() as u32;
+                    let height = (image.height() as f32 * factor).round() as u32;
+                    image.resize_exact(width, height, FilterType::CatmullRom)
+                } else {
+                    image
+                }
+            }
+            PreprocessStep::Denoise => DynamicImage::ImageLuma8(median_filter(&image.to_luma8())),
+        };
+        if let Some(debug_dir) = &config.debug_dir {
+            dump(debug_dir, debug_id, index + 1, step.name(), &image);
+        }
+    }
+
+    Ok(image)
+}
+
+fn dump(debug_dir: &Path, debug_id: u64, index: usize, name: &str, image: &DynamicImage) {
+    let path = debug_dir.join(format!("{:06}-{}-{}.png", debug_id, index, name));
+    if let Err(e) = image.save(&path) {
+        log::warn!("Failed to write preprocessing image {}: {}", path.display(), e);
+    }
+}
+
+fn normalize_contrast(mut image: GrayImage) -> GrayImage {
+    let mut histogram = [0usize; 256];
+    for pixel in image.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+    let clip = (image.pixels().len() as f32 * CONTRAST_CLIP) as usize;
+    let (Some(low), Some(high)) = (first_past(&histogram, 0..256, clip), first_past(&histogram, (0..256).rev(), clip)) else {
+        return image;
+    };
+    if high <= low {
+        return image;
+    }
+
+    let scale = 255.0 / (high - low) as f32;
+    for pixel in image.pixels_mut() {
+        pixel[0] = ((pixel[0] as f32 - low as f32) * scale).clamp(0.0, 255.0) as u8;
+    }
+    image
+}
+
+/// First level in `levels` where the pixel count since the start passes `count`.
+fn first_past(histogram: &[usize; 256], mut levels: impl Iterator<Item = usize>, count: usize) -> Option<usize> {
+    let mut seen = 0;
+    levels.find(|&level| {
+        seen += histogram[level];
+        seen > count
+    })
+}
+
+fn auto_invert(mut image: DynamicImage) -> DynamicImage {
+    let luma = image.to_luma8();
+    let pixels = luma.pixels().len().max(1);
+    let mean = luma.pixels().map(|pixel| pixel[0] as usize).sum::<usize>() / pixels;
+    if mean < 128 {
+        image.invert();
+    }
+    image
+}
+
+/// Pixels darker than the mean of the `window` around them by more than [`THRESHOLD_OFFSET`] become black.
+fn adaptive_threshold(image: &GrayImage, window: u32) -> GrayImage {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    // Sums of all pixels above and left of every position
+    let mut integral = vec![0u64; (width + 1) * (height + 1)];
+    for y in 0..height {
+        let mut row_sum = 0u64;
+        for x in 0..width {
+            row_sum += image.get_pixel(x as u32, y as u32)[0] as u64;
+            integral[(y + 1) * (width + 1) + x + 1] = integral[y * (width + 1) + x + 1] + row_sum;
+        }
+    }
+
+    let radius = (window / 2) as usize;
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        let (left, top) = (x.saturating_sub(radius), y.saturating_sub(radius));
+        let (right, bottom) = ((x + radius + 1).min(width), (y + radius + 1).min(height));
+        let sum = integral[bottom * (width + 1) + right] + integral[top * (width + 1) + left]
+            - integral[top * (width + 1) + right]
+            - integral[bottom * (width + 1) + left];
+        let mean = (sum / ((right - left) * (bottom - top)) as u64) as i32;
+        let value = image.get_pixel(x as u32, y as u32)[0] as i32;
+        Luma([if value < mean - THRESHOLD_OFFSET { 0 } else { 255 }])
+    })
+}
+
+/// 3x3 median filter, the border uses the pixels that exist.
+fn median_filter(image: &GrayImage) -> GrayImage {
+    let (width, height) = (image.width(), image.height());
+    GrayImage::from_fn(width, height, |x, y| {
+        let mut values = [0u8; 9];
+        let mut count = 0;
+        for ny in y.saturating_sub(1)..(y + 2).min(height) {
+            for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                values[count] = image.get_pixel(nx, ny)[0];
+                count += 1;
+            }
+        }
+        let values = &mut values[..count];
+        values.sort_unstable();
+        Luma([values[count / 2]])
+    })
+}
@@ -1,6 +1,11 @@
+use super::preprocess::PreprocessConfig;
+use crate::capture::RegionInfo;
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum OcrModel {
     Tesseract,
     Native,
@@ -40,13 +45,25 @@ impl From<&str> for OcrModel {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct OcrConfig {
     pub ocr_model: OcrModel,
     pub bounding_boxes: Option<bool>, // add normatlized coordinates of the text
     pub dpi: Option<u32>, // dots per inch
     pub psm: Option<u32>, // Page segmentation mode
     pub oem: Option<u32>, // OCR Engine Mode
+    pub frame_coordinates: Option<bool>, // map bounding boxes of cropped regions to the whole frame
+    pub languages: Option<Vec<String>>, // tesseract languages like "eng", "deu" or "jpn", tried together
+    pub config_variables: Option<HashMap<String, String>>, // passed to tesseract as `-c key=value`
+    pub char_whitelist: Option<String>, // only these characters are recognized
+    pub char_blacklist: Option<String>, // these characters are never recognized
+    pub user_words: Option<PathBuf>, // file of extra words, one per line
+    pub user_patterns: Option<PathBuf>, // file of patterns like `\d\d\d-\d\d\d\d`, one per line
+    pub tessdata_path: Option<PathBuf>, // directory of the `.traineddata` files
+    pub min_word_confidence: Option<f32>, // 0-1, words below are dropped
+    pub min_line_confidence: Option<f32>, // 0-1, lines whose mean word confidence is below are dropped
+    pub preprocess: Option<PreprocessConfig>, // image preprocessing ahead of OCR, none if not set
+    pub engine: Option<String>, // name of a registered OcrEngine, overrides ocr_model
 }
 
 impl OcrConfig {
@@ -56,7 +73,19 @@ impl OcrConfig {
             bounding_boxes: Some(true),
             dpi: None,
             psm: None,
-            oem: None
+            oem: None,
+            frame_coordinates: None,
+            languages: None,
+            config_variables: None,
+            char_whitelist: None,
+            char_blacklist: None,
+            user_words: None,
+            user_patterns: None,
+            tessdata_path: None,
+            min_word_confidence: None,
+            min_line_confidence: None,
+            preprocess: None,
+            engine: None,
         }
     }
 
@@ -66,10 +95,105 @@ impl OcrConfig {
             bounding_boxes,
             dpi,
             psm,
-            oem
+            oem,
+            ..Self::default()
         }
     }
 
+    pub fn with_languages(mut self, languages: Vec<String>) -> Self {
+        self.languages = Some(languages);
+        self
+    }
+
+    pub fn with_config_variable(mut self, key: &str, value: &str) -> Self {
+        self.config_variables.get_or_insert_with(HashMap::new).insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn with_char_whitelist(mut self, chars: &str) -> Self {
+        self.char_whitelist = Some(chars.to_string());
+        self
+    }
+
+    pub fn with_char_blacklist(mut self, chars: &str) -> Self {
+        self.char_blacklist = Some(chars.to_string());
+        self
+    }
+
+    pub fn with_user_words(mut self, path: PathBuf) -> Self {
+        self.user_words = Some(path);
+        self
+    }
+
+    pub fn with_user_patterns(mut self, path: PathBuf) -> Self {
+        self.user_patterns = Some(path);
+        self
+    }
+
+    pub fn with_tessdata_path(mut self, path: PathBuf) -> Self {
+        self.tessdata_path = Some(path);
+        self
+    }
+
+    pub fn with_min_confidence(mut self, word: Option<f32>, line: Option<f32>) -> Self {
+        self.min_word_confidence = word;
+        self.min_line_confidence = line;
+        self
+    }
+
+    pub fn with_preprocess(mut self, preprocess: PreprocessConfig) -> Self {
+        self.preprocess = Some(preprocess);
+        self
+    }
+
+    pub fn with_engine(mut self, engine: &str) -> Self {
+        self.engine = Some(engine.to_string());
+        self
+    }
+
+    /// Name of the engine that runs, the `engine` if set or else the one of the `ocr_model`.
+    pub fn get_engine_name(&self) -> String {
+        self.engine.clone().unwrap_or_else(|| self.ocr_model.engine_name().to_string())
+    }
+
+    /// Checks the tesseract settings before any image is processed, e.g. that every language is installed.
+    pub fn validate(&self) -> Result<()> {
+        for (name, confidence) in [("word", self.min_word_confidence), ("line", self.min_line_confidence)] {
+            if confidence.is_some_and(|confidence| !(0.0..=1.0).contains(&confidence)) {
+                anyhow::bail!("Minimum {} confidence {:?} is not within 0-1", name, confidence.unwrap_or_default());
+            }
+        }
+        super::OcrEngineRegistry::global().resolve(self)?;
+        super::ocr_tesseract::validate_tesseract_config(self)
+    }
+
+    pub fn get_languages(&self) -> Vec<String> {
+        match &self.languages {
+            Some(languages) if !languages.is_empty() => languages.clone(),
+            _ => vec![Self::get_default_language()],
+        }
+    }
+
+    pub fn get_min_word_confidence(&self) -> f32 {
+        self.min_word_confidence.unwrap_or(Self::get_default_min_confidence())
+    }
+
+    pub fn get_min_line_confidence(&self) -> f32 {
+        self.min_line_confidence.unwrap_or(Self::get_default_min_confidence())
+    }
+
+    pub fn get_default_min_confidence() -> f32 {
+        0.0
+    }
+
+    pub fn get_default_language() -> String {
+        "eng".to_string()
+    }
+
+    pub fn get_bounding_boxes(&self) -> bool {
+        self.bounding_boxes.unwrap_or(Self::get_default_bounding_boxes())
+    }
+
     pub fn get_default_bounding_boxes() -> bool {
         true
     }
@@ -85,4 +209,279 @@ impl OcrConfig {
     pub fn get_default_oem() -> u32 {
         1
     }
-}
\ No newline at end of file
+
+    pub fn get_default_frame_coordinates() -> bool {
+        false
+    }
+}
+
+/// Box around recognized text, in pixels and as fractions (0-1) of the image size.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct BoundingBox {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+    pub normalized: NormalizedBox,
+}
+
+/// A [`BoundingBox`] as fractions (0-1) of the image size.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct NormalizedBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl BoundingBox {
+    pub fn new(left: u32, top: u32, width: u32, height: u32, image_width: u32, image_height: u32) -> Self {
+        let (image_width, image_height) = (image_width.max(1) as f32, image_height.max(1) as f32);
+        Self {
+            left,
+            top,
+            width,
+            height,
+            normalized: NormalizedBox {
+                x: left as f32 / image_width,
+                y: top as f32 / image_height,
+                width: width as f32 / image_width,
+                height: height as f32 / image_height,
+            },
+        }
+    }
+
+    /// Box around all of `boxes` in an image of the given size, `None` without boxes.
+    pub fn union<'a>(boxes: impl IntoIterator<Item = &'a BoundingBox>, image_width: u32, image_height: u32) -> Option<Self> {
+        boxes
+            .into_iter()
+            .map(|b| (b.left, b.top, b.left + b.width, b.top + b.height))
+            .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+            .map(|(left, top, right, bottom)| Self::new(left, top, right - left, bottom - top, image_width, image_height))
+    }
+
+    /// The box moved by `(x, y)` into an image of the given size, e.g. from a region into its frame.
+    pub fn offset(&self, x: u32, y: u32, image_width: u32, image_height: u32) -> Self {
+        Self::new(self.left + x, self.top + y, self.width, self.height, image_width, image_height)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OcrWord {
+    pub text: String,
+    pub bounding_box: BoundingBox,
+    /// 0-1, `None` if the engine doesn't tell
+    pub confidence: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OcrLine {
+    pub bounding_box: BoundingBox,
+    pub words: Vec<OcrWord>,
+}
+
+impl OcrLine {
+    pub fn text(&self) -> String {
+        self.words.iter().map(|word| word.text.as_str()).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Mean confidence of the words that have one.
+    pub fn confidence(&self) -> Option<f32> {
+        mean(self.words.iter().filter_map(|word| word.confidence))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OcrBlock {
+    pub bounding_box: BoundingBox,
+    pub lines: Vec<OcrLine>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OcrPage {
+    pub bounding_box: BoundingBox,
+    pub blocks: Vec<OcrBlock>,
+}
+
+/// Text OCR found in an image, as pages of blocks of lines of words.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct OcrResult {
+    /// Size of the image the boxes refer to
+    pub width: u32,
+    pub height: u32,
+    pub pages: Vec<OcrPage>,
+    /// Words dropped for a confidence below the configured minimum
+    #[serde(default)]
+    pub dropped_words: usize,
+    /// Aggregate confidence (0-1) of all words OCR found, including the dropped ones
+    #[serde(default)]
+    pub confidence: Option<f32>,
+}
+
+impl OcrResult {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, pages: Vec::new(), dropped_words: 0, confidence: None }
+    }
+
+    /// Result of a single page and block, for engines that only report lines.
+    pub fn from_lines(width: u32, height: u32, lines: Vec<OcrLine>) -> Self {
+        let mut result = Self::new(width, height);
+        if let Some(bounding_box) = BoundingBox::union(lines.iter().map(|line| &line.bounding_box), width, height) {
+            result.pages.push(OcrPage {
+                bounding_box,
+                blocks: vec![OcrBlock { bounding_box, lines }],
+            });
+        }
+        result
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &OcrLine> {
+        self.pages.iter().flat_map(|page| page.blocks.iter()).flat_map(|block| block.lines.iter())
+    }
+
+    pub fn words(&self) -> impl Iterator<Item = &OcrWord> {
+        self.lines().flat_map(|line| line.words.iter())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words().next().is_none()
+    }
+
+    /// Mean confidence of the current words weighted by their length, `None` if the engine
+    /// reports none. See [`OcrResult::confidence`] for the score before filtering.
+    pub fn mean_confidence(&self) -> Option<f32> {
+        let (sum, chars) = self
+            .words()
+            .filter_map(|word| word.confidence.map(|confidence| (confidence, word.text.chars().count().max(1) as f32)))
+            .fold((0.0, 0.0), |(sum, chars), (confidence, len)| (sum + confidence * len, chars + len));
+        (chars > 0.0).then(|| sum / chars)
+    }
+
+    /// Drops words below `min_word_confidence`, then lines below `min_line_confidence`, and
+    /// shrinks the boxes to what is left. Words without a confidence are kept.
+    ///
+    /// [`OcrResult::confidence`] is set from all words first, so dropping the unsure ones
+    /// doesn't inflate it.
+    pub fn retain_confident(&mut self, min_word_confidence: f32, min_line_confidence: f32) {
+        if self.confidence.is_none() {
+            self.confidence = self.mean_confidence();
+        }
+        let is_low = |confidence: Option<f32>, min: f32| confidence.is_some_and(|confidence| confidence < min);
+        let before = self.words().count();
+        for block in self.pages.iter_mut().flat_map(|page| page.blocks.iter_mut()) {
+            for line in &mut block.lines {
+                line.words.retain(|word| !is_low(word.confidence, min_word_confidence));
+            }
+            block.lines.retain(|line| !line.words.is_empty() && !is_low(line.confidence(), min_line_confidence));
+        }
+        let dropped = before - self.words().count();
+        if dropped > 0 {
+            log::debug!("Dropped {} of {} words with low confidence", dropped, before);
+            self.dropped_words += dropped;
+            self.fit_boxes();
+        }
+    }
+
+    /// Shrinks the boxes of lines, blocks and pages to their words and drops empty ones.
+    pub(crate) fn fit_boxes(&mut self) {
+        let (width, height) = (self.width, self.height);
+        for page in &mut self.pages {
+            for block in &mut page.blocks {
+                for line in &mut block.lines {
+                    if let Some(bounding_box) = BoundingBox::union(line.words.iter().map(|word| &word.bounding_box), width, height) {
+                        line.bounding_box = bounding_box;
+                    }
+                }
+                block.lines.retain(|line| !line.words.is_empty());
+                if let Some(bounding_box) = BoundingBox::union(block.lines.iter().map(|line| &line.bounding_box), width, height) {
+                    block.bounding_box = bounding_box;
+                }
+            }
+            page.blocks.retain(|block| !block.lines.is_empty());
+            if let Some(bounding_box) = BoundingBox::union(page.blocks.iter().map(|block| &block.bounding_box), width, height) {
+                page.bounding_box = bounding_box;
+            }
+        }
+        self.pages.retain(|page| !page.blocks.is_empty());
+    }
+
+    /// Plain text in reading order.
+    pub fn text(&self) -> String {
+        self.to_text(false)
+    }
+
+    /// Plain text, every line prefixed with the normalized `(x, y)` of its top-left corner if `bounding_boxes`.
+    pub fn to_text(&self, bounding_boxes: bool) -> String {
+        self.lines()
+            .map(|line| match bounding_boxes {
+                true => format!("({:.2}, {:.2}) {}", line.bounding_box.normalized.x, line.bounding_box.normalized.y, line.text()),
+                false => line.text(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The result with every box moved from `region` into the frame it was cropped from.
+    /// The region image may have been scaled after cropping, so boxes are scaled back to the region first.
+    pub fn to_frame_coordinates(&self, region: &RegionInfo) -> OcrResult {
+        let (width, height) = (region.frame_width, region.frame_height);
+        self.scaled_to(region.width, region.height)
+            .map_boxes(width, height, |bounding_box| bounding_box.offset(region.x, region.y, width, height))
+    }
+
+    /// The result with every box scaled to an image of `width` x `height`, e.g. the original of an upscaled image.
+    pub fn scaled_to(&self, width: u32, height: u32) -> OcrResult {
+        let scale_x = width as f32 / self.width.max(1) as f32;
+        let scale_y = height as f32 / self.height.max(1) as f32;
+        self.map_boxes(width, height, |bounding_box| {
+            BoundingBox::new(
+                (bounding_box.left as f32 * scale_x).round() as u32,
+                (bounding_box.top as f32 * scale_y).round() as u32,
+                (bounding_box.width as f32 * scale_x).round() as u32,
+                (bounding_box.height as f32 * scale_y).round() as u32,
+                width,
+                height,
+            )
+        })
+    }
+
+    fn map_boxes(&self, width: u32, height: u32, map: impl Fn(&BoundingBox) -> BoundingBox) -> OcrResult {
+        OcrResult {
+            width,
+            height,
+            dropped_words: self.dropped_words,
+            confidence: self.confidence,
+            pages: self
+                .pages
+                .iter()
+                .map(|page| OcrPage {
+                    bounding_box: map(&page.bounding_box),
+                    blocks: page
+                        .blocks
+                        .iter()
+                        .map(|block| OcrBlock {
+                            bounding_box: map(&block.bounding_box),
+                            lines: block
+                                .lines
+                                .iter()
+                                .map(|line| OcrLine {
+                                    bounding_box: map(&line.bounding_box),
+                                    words: line
+                                        .words
+                                        .iter()
+                                        .map(|word| OcrWord { bounding_box: map(&word.bounding_box), ..word.clone() })
+                                        .collect(),
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn mean(values: impl Iterator<Item = f32>) -> Option<f32> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f32)
+}
@@ -2,4 +2,4 @@ mod utils;
 
 pub use utils::{calculate_image_difference_luma, calculate_image_difference_rgb, images_differ_rgb};
 
-pub(crate) use utils::{should_process_frame_luma, should_process_frame_rgb, convert_yuv_to_dynamic_image, image_to_base64};
\ No newline at end of file
+pub(crate) use utils::{should_process_frame_luma, should_process_frame_rgb, convert_yuv_to_dynamic_image, image_to_base64, scale_image};
\ No newline at end of file
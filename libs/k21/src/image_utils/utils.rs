@@ -109,6 +109,26 @@ pub fn should_process_frame_rgb(current_image: &RgbImage, previous_image: Option
     }
 }
         
+/// Scales an image down by `quality` percent (1-100).
+pub fn scale_image(original: DynamicImage, quality: u8, filter: image::imageops::FilterType) -> DynamicImage {
+    let quality = quality.clamp(1, 100);
+    if quality == 100 {
+        return original;
+    }
+
+    // Convert quality (1-100) to scale factor
+    // 100 = original size (scale by 1.0)
+    // 50 = half size (scale by 0.5)
+    // 1 = smallest (scale by 0.01)
+    let scale = quality as f32 / 100.0;
+
+    original.resize(
+        (original.width() as f32 * scale) as u32,
+        (original.height() as f32 * scale) as u32,
+        filter
+    )
+}
+
 pub fn image_to_base64(image: &DynamicImage) -> Result<String> {
     let mut buffer = Vec::new();
     let mut cursor = std::io::Cursor::new(&mut buffer);
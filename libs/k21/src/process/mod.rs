@@ -1,7 +1,9 @@
 mod utils;
 
 pub use utils::capture_and_process_screen;
+pub use utils::capture_and_process_source;
 pub use utils::process_image_by_processing_type;
 pub use utils::process_image;
+pub use utils::IMAGE2TEXT_CONSUMER;
 mod types;
 pub use types::*;
\ No newline at end of file
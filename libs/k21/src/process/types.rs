@@ -1,11 +1,15 @@
 use crate::{common::ProcessingType, image2text::OcrConfig};
 use crate::image2text::VisionConfig;
+use crate::capture::BackpressurePolicy;
+use anyhow::Result;
 
 #[derive(Clone)]
 pub struct ProcessorConfig {
     pub processing_type: ProcessingType,
     pub vision_config: Option<VisionConfig>,
     pub ocr_config: Option<OcrConfig>,
+    /// What happens to captured frames while processing falls behind, blocks capturing by default
+    pub backpressure: Option<BackpressurePolicy>,
 }
 
 impl ProcessorConfig {
@@ -13,7 +17,8 @@ impl ProcessorConfig {
         Self {
             processing_type,
             vision_config,
-            ocr_config
+            ocr_config,
+            backpressure: None,
         }
     }
 
@@ -22,6 +27,19 @@ impl ProcessorConfig {
             processing_type: ProcessingType::OCR,
             vision_config: None,
             ocr_config: Some(OcrConfig::default()),
+            backpressure: None,
         }
     }
+
+    /// Fails on OCR settings that would fail on every frame, like a missing language.
+    pub fn validate(&self) -> Result<()> {
+        match (&self.processing_type, &self.ocr_config) {
+            (ProcessingType::OCR, Some(ocr_config)) => ocr_config.validate(),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn get_backpressure(&self) -> BackpressurePolicy {
+        self.backpressure.unwrap_or_default()
+    }
 }
\ No newline at end of file
@@ -1,60 +1,92 @@
 use crate::common::get_results_from_state;
 use crate::image2text::process_image_vision;
 use crate::image_utils::image_to_base64;
-use crate::image2text::process_ocr;
-use crate::common::get_current_timestamp_str;
+use crate::image2text::{recognize_text, OcrConfig, OcrResult};
+use crate::common::TIMESTAMP_FORMAT;
 use crate::image_utils::should_process_frame_rgb;
-use crate::capture::ScreenCaptureConfig;
-use crate::capture::spawn_screenshot_task;
+use crate::capture::{CapturedFrame, FrameSource, ScreenCaptureConfig};
+use crate::capture::{spawn_frame_source_task, spawn_screenshot_task, FrameFanout, FrameReceiver, RECORDER_CONSUMER};
 use crate::common::ImageData;
 use crate::common::ProcessingType;
-use tokio::sync::broadcast::channel;
+use tokio::task::JoinSet;
 use crate::common::ImageDataCollection;
 use crate::capture::handle_captured_frames;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use image::DynamicImage;
+use image::{DynamicImage, RgbImage};
+use anyhow::Result;
 
 use tokio::sync::watch;
 
 use super::ProcessorConfig;
 
 const THRESHOLD: f32 = 0.05;
+const MAX_IN_FLIGHT_DEFAULT: usize = 4;
 
-pub async fn capture_and_process_screen(screen_capture_config: &ScreenCaptureConfig, processor_config: &ProcessorConfig) -> ImageDataCollection {
+/// Consumer that runs OCR or vision on the captured frames
+pub const IMAGE2TEXT_CONSUMER: &str = "image2text";
+
+pub async fn capture_and_process_screen(screen_capture_config: &ScreenCaptureConfig, processor_config: &ProcessorConfig) -> Result<ImageDataCollection> {
+    capture_and_process(screen_capture_config, processor_config, None).await
+}
+
+/// Same as [`capture_and_process_screen`], but pulls frames from `source` instead of the
+/// target configured in `screen_capture_config`.
+pub async fn capture_and_process_source(
+    source: Box<dyn FrameSource>,
+    screen_capture_config: &ScreenCaptureConfig,
+    processor_config: &ProcessorConfig
+) -> Result<ImageDataCollection> {
+    capture_and_process(screen_capture_config, processor_config, Some(source)).await
+}
+
+async fn capture_and_process(
+    screen_capture_config: &ScreenCaptureConfig,
+    processor_config: &ProcessorConfig,
+    source: Option<Box<dyn FrameSource>>
+) -> Result<ImageDataCollection> {
+    processor_config.validate()?;
+    if let Some(adaptive_fps) = screen_capture_config.get_adaptive_fps() {
+        adaptive_fps.validate()?;
+    }
     log::debug!("Starting capture at {} fps", screen_capture_config.get_fps());
 
     let results_arc = Arc::new(Mutex::new(ImageDataCollection::new()));
 
-    // channel for screenshot capture task
-    let (screenshot_tx, mut screenshot_rx) = channel(512);
-    let mut screenshot_rx_clone = screenshot_rx.resubscribe();
+    // every consumer gets its own queue of the captured frames
+    let capacity = screen_capture_config.get_frame_queue_capacity();
+    let mut fanout = FrameFanout::new();
+    let image2text_rx = fanout.subscribe(IMAGE2TEXT_CONSUMER, processor_config.get_backpressure(), capacity);
+    let recorder_rx = fanout.subscribe(
+        RECORDER_CONSUMER,
+        screen_capture_config.get_recorder_backpressure(),
+        capacity,
+    );
+    let stats = fanout.stats();
 
     // channel for closing the capture task
-    let (close_tx, close_rx) = watch::channel(false);
-    let close_rx_clone = close_rx.clone();
+    let (close_tx, _close_rx) = watch::channel(false);
+    let (outside_schedule_tx, outside_schedule_rx) = watch::channel(false);
 
-
-    let screenshot_task = spawn_screenshot_task(
-        screen_capture_config,
-        screenshot_tx,
-        close_tx
-    );
+    let screenshot_task = match source {
+        Some(source) => spawn_frame_source_task(screen_capture_config, source, fanout, close_tx, outside_schedule_tx),
+        None => spawn_screenshot_task(screen_capture_config, fanout, close_tx, outside_schedule_tx),
+    };
 
     let image2text_tasks = process_image2text_screenshots_task(
         &processor_config,
-        &mut screenshot_rx, 
-        close_rx,
+        image2text_rx,
         results_arc.clone(),
     );
 
     let handle_captured_frames_task = handle_captured_frames(
         screen_capture_config,
         false,
-        &mut screenshot_rx_clone,
-        close_rx_clone,
+        recorder_rx,
+        outside_schedule_rx,
     );
 
-    let (_, ocr_result) = tokio::join!(
+    let (_, mut ocr_tasks) = tokio::join!(
         handle_captured_frames_task,
         image2text_tasks
     );
@@ -62,29 +94,31 @@ pub async fn capture_and_process_screen(screen_capture_config: &ScreenCaptureCon
     if let Err(e) = screenshot_task.await {
         log::error!("Screenshot task failed: {:?}", e);
     }
+    stats.log_summary();
 
-    for (i, task) in ocr_result.into_iter().enumerate() {
-        if let Err(e) = task.await {
-            log::error!("Image2Text task {} failed: {:?}", i, e);
-        }
+    while let Some(result) = ocr_tasks.join_next().await {
+        log_task_result(Some(result));
     }
 
     let results = get_results_from_state(results_arc).await.unwrap();
     log::debug!("Collected {} Image2Text results", results.len());
 
-    results
+    Ok(results)
 }
 
+/// Text of `image`, and for OCR the words and lines it was rendered from.
 pub async fn process_image_by_processing_type(
     image: &DynamicImage,
     processor_config: &ProcessorConfig,
     frame_number: u64,
-) -> Option<String> {
+) -> Option<(String, Option<OcrResult>)> {
     match &processor_config.processing_type {
         ProcessingType::OCR => {
             let ocr_config = processor_config.ocr_config.as_ref().unwrap();
-            match process_ocr(image, ocr_config).await {
-                Ok(text) if !text.is_empty() => Some(text),
+            match recognize_text(image, ocr_config).await {
+                Ok(result) if !result.is_empty() => {
+                    Some((result.to_text(ocr_config.get_bounding_boxes()), Some(result)))
+                },
                 Ok(_) => {
                     log::debug!("No text detected in frame {}", frame_number);
                     None
@@ -101,25 +135,41 @@ pub async fn process_image_by_processing_type(
                 image_to_base64(image).unwrap(), 
                 &vision_config
             ).await;
-            Some(result)
+            Some((result, None))
         }
     }
 }
 
 pub async fn process_image(
     processor_config: &ProcessorConfig,
-    image: &DynamicImage,
-    frame_number: u64,
+    frame: &CapturedFrame,
     results_arc: Arc<Mutex<ImageDataCollection>>
 ) {
     let processing_type = &processor_config.processing_type;
+    let frame_number = frame.frame_number;
     
-    let result = process_image_by_processing_type(image, processor_config, frame_number).await;
+    let result = process_image_by_processing_type(&frame.image, processor_config, frame_number).await;
+    let result = match (result, &frame.region, &processor_config.ocr_config) {
+        (Some((_, Some(ocr))), Some(region), Some(ocr_config))
+            if ocr_config.frame_coordinates.unwrap_or(OcrConfig::get_default_frame_coordinates()) =>
+        {
+            let ocr = ocr.to_frame_coordinates(region);
+            Some((ocr.to_text(ocr_config.get_bounding_boxes()), Some(ocr)))
+        }
+        (result, _, _) => result,
+    };
 
-    if let Some(text) = result {
-        let timestamp: String = get_current_timestamp_str();
+    if let Some((text, ocr)) = result {
+        let timestamp: String = frame.captured_at.format(TIMESTAMP_FORMAT).to_string();
         let processing_type_clone = processing_type.clone();
-        let image_data: ImageData = ImageData::new(timestamp, frame_number, text, processing_type_clone);
+        let image_data: ImageData = ImageData::new(timestamp, frame_number, text, processing_type_clone)
+            .with_monitor_id(frame.monitor_id)
+            .with_window(
+                frame.window.as_ref().map(|w| w.title.clone()),
+                frame.window.as_ref().map(|w| w.app_name.clone()),
+            )
+            .with_region(frame.region.clone())
+            .with_ocr(ocr);
         
         if let Ok(mut results) = results_arc.lock() {
             results.push(image_data);
@@ -129,58 +179,60 @@ pub async fn process_image(
     }
 }
 
+/// Runs image2text on the frames of `frames_rx`, at most one frame per CPU at a time so the
+/// consumer's backpressure policy applies once processing falls behind.
 async fn process_image2text_screenshots_task(
     processor_config: &ProcessorConfig,
-    screenshot_rx: &mut tokio::sync::broadcast::Receiver<(u64, DynamicImage)>,
-    mut close_rx: tokio::sync::watch::Receiver<bool>,
+    mut frames_rx: FrameReceiver,
     results_arc: Arc<Mutex<ImageDataCollection>>
-) -> Vec<tokio::task::JoinHandle<()>> {
-    let mut tasks = Vec::new();
-    let mut previous_image: Option<DynamicImage> = None;
-
-    loop {
-        tokio::select! {
-            Ok((frame_number, image)) = screenshot_rx.recv() => {
-                log::debug!("Processing frame {} with {:?}", frame_number, processor_config.processing_type);
-
-                let current_rgb = image.to_rgb8();
-                let previous_rgb = previous_image.as_ref().map(|img| img.to_rgb8());
-
-                let should_process = should_process_frame_rgb(
-                    &current_rgb,
-                    previous_rgb.as_ref(),
-                    THRESHOLD
-                );
-
-                if !should_process {
-                    log::debug!("Images similar, skipping frame {}", frame_number);
-                    continue;
-                }
+) -> JoinSet<()> {
+    let mut tasks = JoinSet::new();
+    let max_in_flight = std::thread::available_parallelism().map_or(MAX_IN_FLIGHT_DEFAULT, |n| n.get());
+    // Last processed image of every stream, so monitors and windows are compared against themselves
+    let mut previous_images: HashMap<Option<String>, RgbImage> = HashMap::new();
+
+    while let Some(frame) = frames_rx.recv().await {
+        let frame_number = frame.frame_number;
+        let stream_name = frame.stream_name();
+        log::debug!("Processing frame {} of stream {:?} with {:?}", frame_number, stream_name, processor_config.processing_type);
+
+        let current_rgb = frame.image.to_rgb8();
+
+        let should_process = should_process_frame_rgb(
+            &current_rgb,
+            previous_images.get(&stream_name),
+            THRESHOLD
+        );
+
+        if !should_process {
+            log::debug!("Images similar, skipping frame {}", frame_number);
+            continue;
+        }
 
-                let image_clone = image.clone();
-                let processor_config = processor_config.clone();
-                let results_arc_clone = results_arc.clone();
-
-                let task = tokio::task::spawn(async move {
-                    process_image(
-                        &processor_config,
-                        &image_clone,
-                        frame_number,
-                        results_arc_clone
-                    ).await;
-                });
-                
-                tasks.push(task);
-                previous_image = Some(image.clone());
-            }
-            Ok(_) = close_rx.changed() => {
-                if *close_rx.borrow() {
-                    log::debug!("Screenshot channel closed, stopping processing");
-                    break;
-                }
-            }
+        previous_images.insert(stream_name, current_rgb);
+
+        while tasks.len() >= max_in_flight {
+            log_task_result(tasks.join_next().await);
         }
+
+        let processor_config = processor_config.clone();
+        let results_arc_clone = results_arc.clone();
+
+        tasks.spawn(async move {
+            process_image(
+                &processor_config,
+                &frame,
+                results_arc_clone
+            ).await;
+        });
     }
+    log::debug!("Capture ended, all frames dispatched for processing");
 
     tasks
-}
\ No newline at end of file
+}
+
+fn log_task_result(result: Option<Result<(), tokio::task::JoinError>>) {
+    if let Some(Err(e)) = result {
+        log::error!("Image2Text task failed: {:?}", e);
+    }
+}
@@ -12,8 +12,9 @@ pub fn path_to_image(path: &str) -> Result<DynamicImage> {
 pub async fn process_image(path: String, config: &ProcessorConfig) -> Result<ImageDataCollection> {
     let image = path_to_image(&path)?;
     
-    let result = process_image_by_processing_type(&image, config, 0).await;
-    let image_data = ImageData::new(get_current_timestamp_str(), 0, result.unwrap(), config.processing_type.clone());
+    let (text, ocr) = process_image_by_processing_type(&image, config, 0).await.unwrap_or_default();
+    let image_data = ImageData::new(get_current_timestamp_str(), 0, text, config.processing_type.clone())
+        .with_ocr(ocr);
     
     let mut image_data_collection = ImageDataCollection::new();
     image_data_collection.push(image_data);
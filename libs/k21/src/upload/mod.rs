@@ -10,6 +10,7 @@ mod video;
 pub use video::process_mp4_from_base64_with_state;
 pub use video::process_mp4_buffer_path;
 pub use video::process_mp4;
+pub(crate) use video::Mp4FrameDecoder;
 
 use crate::{common::ImageDataCollection, process::ProcessorConfig};
 
@@ -31,6 +32,7 @@ fn get_upload_type(path: &str) -> Result<UploadType> {
 
 pub async fn process_upload(path: String, config: &ProcessorConfig) -> Result<ImageDataCollection> {
     let upload_type = get_upload_type(&path)?;
+    config.validate()?;
     match upload_type {
         UploadType::Image => process_image(path, config).await,
         UploadType::Video => process_mp4(path, config).await,
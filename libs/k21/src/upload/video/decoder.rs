@@ -0,0 +1,196 @@
+use std::collections::VecDeque;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, TimeZone};
+use image::DynamicImage;
+use openh264::decoder::{Decoder, DecoderConfig, Flush};
+
+use super::bitstream_converter::Mp4BitstreamConverter;
+use crate::capture::{MP4_EPOCH_OFFSET, START_TIME_BOX};
+use crate::image_utils::convert_yuv_to_dynamic_image;
+
+/// A frame decoded from an MP4 file.
+pub struct DecodedFrame {
+    /// 1-based sample the frame was decoded from, `None` for frames flushed out at the end.
+    pub sample_number: Option<u32>,
+    /// Wall-clock time the frame was captured, if the recording tells when it started.
+    pub captured_at: Option<DateTime<Local>>,
+    pub image: DynamicImage,
+}
+
+/// Decodes the H.264 track of an MP4 frame by frame.
+///
+/// Every sample is fed to the decoder, since P-frames only decode on top of the frames before
+/// them. Callers that only want some frames should skip them after decoding.
+pub struct Mp4FrameDecoder<R> {
+    mp4: mp4::Mp4Reader<R>,
+    track_id: u32,
+    sample_count: u32,
+    timescale: u32,
+    start_time: Option<DateTime<Local>>,
+    // Capture times of the samples fed to the decoder that haven't come out as frames yet
+    pending_times: VecDeque<Option<DateTime<Local>>>,
+    next_sample: u32,
+    bitstream_converter: Mp4BitstreamConverter,
+    decoder: Decoder,
+    buffer: Vec<u8>,
+    flushed: Option<VecDeque<DynamicImage>>,
+}
+
+impl<R: Read + Seek> Mp4FrameDecoder<R> {
+    pub fn new(mut reader: R, size: u64) -> Result<Self> {
+        let start_time_millis = read_start_time_millis(&mut reader, size)?;
+        reader.seek(SeekFrom::Start(0))?;
+        let mp4 = mp4::Mp4Reader::read_header(reader, size)?;
+
+        // Fall back to the second precision creation time, files of other tools may not have one
+        let start_time = match start_time_millis {
+            Some(millis) => Local.timestamp_millis_opt(millis).single(),
+            None => match mp4.moov.mvhd.creation_time as i64 - MP4_EPOCH_OFFSET {
+                seconds if seconds > 0 => Local.timestamp_opt(seconds, 0).single(),
+                _ => None,
+            },
+        };
+
+        let track = mp4
+            .tracks()
+            .iter()
+            .find(|(_, t)| t.media_type().is_ok_and(|m| m == mp4::MediaType::H264))
+            .ok_or_else(|| anyhow!("No H264 track found"))?
+            .1;
+        let track_id = track.track_id();
+        let sample_count = track.sample_count();
+        let timescale = track.timescale();
+
+        let decoder_options = DecoderConfig::new()
+            .debug(false)
+            .flush_after_decode(Flush::NoFlush);
+
+        let bitstream_converter = Mp4BitstreamConverter::for_mp4_track(track)?;
+        let decoder = Decoder::with_api_config(openh264::OpenH264API::from_source(), decoder_options)?;
+
+        Ok(Self {
+            mp4,
+            track_id,
+            sample_count,
+            timescale,
+            start_time,
+            pending_times: VecDeque::new(),
+            next_sample: 1,
+            bitstream_converter,
+            decoder,
+            buffer: Vec::new(),
+            flushed: None,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.mp4.duration()
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Decodes the next frame, `Ok(None)` once the track is exhausted.
+    pub fn next_frame(&mut self) -> Result<Option<DecodedFrame>> {
+        while self.next_sample <= self.sample_count {
+            let i = self.next_sample;
+            self.next_sample += 1;
+
+            let sample = match self.mp4.read_sample(self.track_id, i)? {
+                Some(sample) => sample,
+                None => continue,
+            };
+
+            self.bitstream_converter.convert_packet(&sample.bytes, &mut self.buffer);
+            self.pending_times.push_back(self.sample_time(sample.start_time));
+
+            match self.decoder.decode(&self.buffer) {
+                Ok(Some(yuv)) => {
+                    let (image, _) = convert_yuv_to_dynamic_image(&yuv)?;
+                    let captured_at = self.pending_times.pop_front().flatten();
+                    return Ok(Some(DecodedFrame { sample_number: Some(i), captured_at, image }));
+                }
+                Ok(None) => continue,
+                Err(err) => {
+                    log::error!("error frame {i}: {err}");
+                    self.pending_times.pop_back();
+                }
+            }
+        }
+
+        if self.flushed.is_none() {
+            let remaining = self
+                .decoder
+                .flush_remaining()?
+                .iter()
+                .map(|yuv| convert_yuv_to_dynamic_image(yuv).map(|(image, _)| image))
+                .collect::<Result<VecDeque<_>>>()?;
+            self.flushed = Some(remaining);
+        }
+
+        let Some(image) = self.flushed.as_mut().and_then(|remaining| remaining.pop_front()) else {
+            return Ok(None);
+        };
+        let captured_at = self.pending_times.pop_front().flatten();
+        Ok(Some(DecodedFrame { sample_number: None, captured_at, image }))
+    }
+
+    fn sample_time(&self, start_time: u64) -> Option<DateTime<Local>> {
+        let offset = Duration::from_secs_f64(start_time as f64 / self.timescale.max(1) as f64);
+        Some(self.start_time? + chrono::Duration::from_std(offset).ok()?)
+    }
+}
+
+/// Reads the millisecond start time k21 keeps in the user data of the `moov` box.
+fn read_start_time_millis<R: Read + Seek>(reader: &mut R, size: u64) -> Result<Option<i64>> {
+    let mut offset = 0;
+    while offset + 8 <= size {
+        let mut header = [0u8; 8];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut header)?;
+        let box_size = u32::from_be_bytes(header[..4].try_into()?) as u64;
+
+        if &header[4..] == b"moov" && box_size >= 8 {
+            let mut moov = vec![0u8; box_size as usize - 8];
+            reader.read_exact(&mut moov)?;
+            let start_time = find_box(&moov, b"udta")
+                .and_then(|udta| find_box(udta, START_TIME_BOX))
+                .and_then(|start| start.get(4..12))
+                .map(|millis| i64::from_be_bytes(millis.try_into().unwrap()));
+            return Ok(start_time);
+        }
+
+        offset += match box_size {
+            // 64-bit size follows the header
+            1 => {
+                let mut large_size = [0u8; 8];
+                reader.read_exact(&mut large_size)?;
+                u64::from_be_bytes(large_size)
+            }
+            // Runs until the end of the file
+            0 => break,
+            size => size,
+        };
+    }
+    Ok(None)
+}
+
+/// Content of the first child box named `name`.
+fn find_box<'a>(data: &'a [u8], name: &[u8; 4]) -> Option<&'a [u8]> {
+    let mut offset = 0;
+    while offset + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[offset..offset + 4].try_into().ok()?) as usize;
+        if size < 8 || offset + size > data.len() {
+            return None;
+        }
+        if &data[offset + 4..offset + 8] == name {
+            return Some(&data[offset + 8..offset + size]);
+        }
+        offset += size;
+    }
+    None
+}
@@ -4,4 +4,7 @@ pub use utils::process_mp4_from_base64_with_state;
 pub use utils::process_mp4_buffer_path;
 pub use utils::process_mp4;
 
-mod bitstream_converter;
\ No newline at end of file
+mod bitstream_converter;
+
+mod decoder;
+pub(crate) use decoder::Mp4FrameDecoder;
\ No newline at end of file
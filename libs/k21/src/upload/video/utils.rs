@@ -5,12 +5,11 @@ use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-use anyhow::{anyhow, Result};
-use openh264::decoder::{Decoder, DecoderConfig, Flush};
+use anyhow::Result;
 
-use super::bitstream_converter::Mp4BitstreamConverter;
+use super::decoder::Mp4FrameDecoder;
+use crate::capture::{CapturedFrame, ChunkManifest};
 use crate::common::{decode_base64, get_results_from_state, ImageDataCollection};
-use crate::image_utils::convert_yuv_to_dynamic_image;
 use crate::image_utils::should_process_frame_luma;
 use crate::process::{process_image, ProcessorConfig};
 // Module-level constantd
@@ -23,7 +22,22 @@ pub async fn process_mp4_buffer_path(
 ) -> Result<()>
 {
     let mp4_data = from_file_path_to_mp4_reader(path).await?;
-    process_mp4_buffer(&mp4_data, config, state).await?;
+
+    // Recordings of k21 come with a manifest telling which frame was captured when and where
+    let manifest = match ChunkManifest::read_for(path) {
+        Ok(Some(manifest)) if manifest.matches(&mp4_data) => Some(manifest),
+        Ok(Some(_)) => {
+            log::warn!("Manifest of {} doesn't match the video, ignoring it", path.display());
+            None
+        }
+        Ok(None) => None,
+        Err(e) => {
+            log::warn!("Failed to read manifest of {}: {}", path.display(), e);
+            None
+        }
+    };
+
+    process_mp4_buffer_with_manifest(&mp4_data, manifest.as_ref(), config, state).await?;
     Ok(())
 }
 
@@ -59,27 +73,28 @@ pub async fn process_mp4_from_base64_with_state(
 }
 
 pub async fn process_mp4_buffer(mp4_data: &[u8], config: &ProcessorConfig, state: Arc<Mutex<ImageDataCollection>>) -> Result<()>
+{
+    process_mp4_buffer_with_manifest(mp4_data, None, config, state).await
+}
+
+pub async fn process_mp4_buffer_with_manifest(
+    mp4_data: &[u8],
+    manifest: Option<&ChunkManifest>,
+    config: &ProcessorConfig,
+    state: Arc<Mutex<ImageDataCollection>>
+) -> Result<()>
 {
     let total_start = Instant::now();
     
     log::info!("Processing MP4 frames start of Reader");
-    let data = mp4_data.as_ref();
-    let mut mp4 = mp4::Mp4Reader::read_header(Cursor::new(data), data.len() as u64)?;
+    let mut frames = Mp4FrameDecoder::new(Cursor::new(mp4_data), mp4_data.len() as u64)?;
 
     // Get MP4 duration in seconds
-    let duration = mp4.duration();
-    let duration_seconds = duration.as_secs_f64();
+    let duration_seconds = frames.duration().as_secs_f64();
     log::info!("MP4 duration: {:?}", duration_seconds);
 
     // Calculate frame step based on track sample count and duration
-    let track = mp4
-        .tracks()
-        .iter()
-        .find(|(_, t)| t.media_type().unwrap() == mp4::MediaType::H264)
-        .ok_or_else(|| anyhow!("Must exist"))?
-        .1;
-    let track_id = track.track_id();
-    let sample_count = track.sample_count();
+    let sample_count = frames.sample_count();
     let step = if duration_seconds > 0.0 {
         (sample_count as f64 / duration_seconds).ceil() as usize
     } else {
@@ -87,62 +102,34 @@ pub async fn process_mp4_buffer(mp4_data: &[u8], config: &ProcessorConfig, state
     };
     log::info!("Processing with step size: {}, total samples: {}", step, sample_count);
 
-    let decoder_options = DecoderConfig::new()
-        .debug(false)
-        .flush_after_decode(Flush::NoFlush);
-
-    let mut bitstream_converter = Mp4BitstreamConverter::for_mp4_track(track)?;
-    let mut decoder =
-        Decoder::with_api_config(openh264::OpenH264API::from_source(), decoder_options).unwrap();
-
-    let mut buffer = Vec::new();
     let mut frame_idx = 0u32;
+    let mut decoded_count = 0usize;
     let mut previous_image: Option<Vec<u8>> = None;
 
-    for i in 1..=track.sample_count() {
-        let sample = mp4.read_sample(track_id, i)?;
-
-        let sample = match sample {
-            Some(sample) => sample,
-            None => continue,
-        };
-
-        bitstream_converter.convert_packet(&sample.bytes, &mut buffer);
-        
-        match decoder.decode(&buffer) {
-            Ok(Some(yuv)) => {
-                // Skip frames based on step size (early exit)
-                if i % step as u32 != 0 {
-                    continue;
-                }
-                log::info!("Processing frame {}", i);
-
-                let (current_dynamic_image, current_luma) = convert_yuv_to_dynamic_image(&yuv)?;
-                
-                if should_process_frame_luma(&current_luma, previous_image.as_deref(), THRESHOLD_VALUE) {
-                    process_image(config, &current_dynamic_image, frame_idx as u64, state.clone()).await;
-                    previous_image = Some(current_luma.to_vec());
-
-                } else {
-                    log::info!("Frame {} skipped - no significant changes", frame_idx);
-                }
-                frame_idx += 1;
-            }
-            Ok(None) => continue,
-            Err(err) => {
-                log::error!("error frame {i}: {err}");
-            }
-        }
-    }
+    while let Some(decoded) = frames.next_frame()? {
+        let sample_index = decoded.sample_number.map_or(decoded_count, |i| i as usize - 1);
+        decoded_count += 1;
 
-    for yuv in decoder.flush_remaining()? {
-        log::info!("Flushing frame {frame_idx}");
+        match decoded.sample_number {
+            // Skip frames based on step size (early exit)
+            Some(i) if i % step as u32 != 0 => continue,
+            Some(i) => log::info!("Processing frame {}", i),
+            None => log::info!("Flushing frame {frame_idx}"),
+        }
 
-        let (current_dynamic_image, current_luma) = convert_yuv_to_dynamic_image(&yuv)?;
+        let current_luma = decoded.image.as_bytes().to_vec();
 
         if should_process_frame_luma(&current_luma, previous_image.as_deref(), THRESHOLD_VALUE) {
-            process_image(config, &current_dynamic_image, frame_idx as u64, state.clone()).await;
-            previous_image = Some(current_luma.to_vec());
+            let mut frame = CapturedFrame::new(frame_idx as u64, decoded.image);
+            // Recordings tell when each frame was on screen, that's what the results should carry
+            if let Some(captured_at) = decoded.captured_at {
+                frame = frame.with_captured_at(captured_at);
+            }
+            if let Some(manifest) = manifest {
+                frame = with_manifest_metadata(frame, manifest, sample_index);
+            }
+            process_image(config, &frame, state.clone()).await;
+            previous_image = Some(current_luma);
         } else {
             log::info!("Frame {} skipped - no significant changes", frame_idx);
         }
@@ -153,6 +140,28 @@ pub async fn process_mp4_buffer(mp4_data: &[u8], config: &ProcessorConfig, state
     Ok(())
 }
 
+/// Tags a frame with the capture metadata the manifest has for its sample.
+fn with_manifest_metadata(mut frame: CapturedFrame, manifest: &ChunkManifest, sample_index: usize) -> CapturedFrame {
+    if let Some(frame_time) = manifest.frame(sample_index) {
+        frame.frame_number = frame_time.frame_number;
+        frame = frame.with_captured_at(frame_time.captured_at);
+    }
+    if let Some(monitor) = &manifest.monitor {
+        frame = frame.with_monitor(monitor.clone());
+    }
+    if let Some(window) = &manifest.window {
+        frame = frame.with_window(window.clone());
+    }
+    if let Some(region) = &manifest.region {
+        frame = frame.with_region(region.clone());
+    }
+    // Odd sized frames were padded for the encoder, results should only cover what was captured
+    let (width, height) = (manifest.width, manifest.height);
+    if width > 0 && height > 0 && (width < frame.image.width() || height < frame.image.height()) {
+        frame.image = frame.image.crop_imm(0, 0, width.min(frame.image.width()), height.min(frame.image.height()));
+    }
+    frame
+}
 
 async fn from_file_path_to_mp4_reader(path: &PathBuf) -> Result<std::vec::Vec<u8>>
 {
@@ -1,16 +1,18 @@
 use clap::Parser;
-use image::{DynamicImage, RgbImage};
+use image::RgbImage;
+use k21::capture::{CapturedFrame, FrameDecoder, RawPixelFormat, RawVideoDecoder, Y4mDecoder};
 use k21::image_utils::images_differ_rgb;
 use k21::upload::process_mp4_buffer_path;
-use k21::image2text::{process_ocr, OcrConfig};
+use k21::image2text::{process_ocr, OcrConfig, PreprocessConfig, PreprocessStep};
 use k21::logger::init_logger_exe;
 use k21::process::ProcessorConfig;
 use k21::common::ImageDataCollection;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::io::{self, AsyncReadExt, BufReader};
+use tokio::io::{self, AsyncRead, BufReader};
 
 #[derive(Parser)]
 #[command(version, about = "A CLI tool to OCR image/video", long_about = None)]
@@ -24,6 +26,146 @@ struct Cli {
     mp4: Option<PathBuf>,
     #[arg(long, help = "get image from stdin (from screen)")]
     stdin: bool,
+    #[arg(
+        long,
+        help = "format of frames on stdin: k21 (from k21-screen), y4m or rawvideo",
+        default_value = "k21",
+        value_parser
+    )]
+    stdin_format: StdinFormat,
+    #[arg(long, help = "frame width of rawvideo input")]
+    width: Option<u32>,
+    #[arg(long, help = "frame height of rawvideo input")]
+    height: Option<u32>,
+    #[arg(
+        long,
+        help = "pixel format of rawvideo input: rgb24, bgr24, rgba, bgra, gray, yuv420p or yuv444p",
+        default_value = "rgb24",
+        value_parser
+    )]
+    pix_fmt: RawPixelFormat,
+    #[arg(
+        long = "lang",
+        help = "tesseract language like eng, deu or jpn, can be repeated to recognize several at once (default: eng)",
+        value_parser
+    )]
+    languages: Vec<String>,
+    #[arg(
+        long = "tesseract-config",
+        help = "tesseract config variable as key=value, can be repeated",
+        value_parser = parse_config_variable
+    )]
+    config_variables: Vec<(String, String)>,
+    #[arg(long, help = "only recognize these characters")]
+    whitelist: Option<String>,
+    #[arg(long, help = "never recognize these characters")]
+    blacklist: Option<String>,
+    #[arg(long, help = "file of extra words for tesseract, one per line")]
+    user_words: Option<PathBuf>,
+    #[arg(long, help = "file of patterns for tesseract like \\d\\d\\d-\\d\\d\\d\\d, one per line")]
+    user_patterns: Option<PathBuf>,
+    #[arg(long, help = "directory of the tesseract .traineddata files")]
+    tessdata: Option<PathBuf>,
+    #[arg(long, help = "drop words with an OCR confidence below this (0-1)")]
+    min_confidence: Option<f32>,
+    #[arg(long, help = "drop lines with a mean OCR confidence below this (0-1)")]
+    min_line_confidence: Option<f32>,
+    #[arg(
+        long,
+        help = "preprocess images before OCR with these comma separated steps: grayscale, contrast, invert, threshold, upscale, denoise",
+        value_delimiter = ',',
+        value_parser
+    )]
+    preprocess: Vec<PreprocessStep>,
+    #[arg(long, help = "DPI of the captured screen, upscale scales it to --target-dpi (default: 96)")]
+    screen_dpi: Option<u32>,
+    #[arg(long, help = "DPI upscale scales the screen to (default: 300)")]
+    target_dpi: Option<u32>,
+    #[arg(long, help = "write the image after every preprocessing step to this directory")]
+    preprocess_debug: Option<PathBuf>,
+    #[arg(long, help = "OCR engine: tesseract, or macos/windows on those platforms (default: the native engine)")]
+    engine: Option<String>,
+}
+
+fn parse_config_variable(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("{:?} is not key=value", s))
+}
+
+fn ocr_config(cli: &Cli) -> OcrConfig {
+    let mut config = OcrConfig::default();
+    config.engine = cli.engine.clone();
+    if !cli.languages.is_empty() {
+        config = config.with_languages(cli.languages.clone());
+    }
+    for (key, value) in &cli.config_variables {
+        config = config.with_config_variable(key, value);
+    }
+    config.char_whitelist = cli.whitelist.clone();
+    config.char_blacklist = cli.blacklist.clone();
+    config.user_words = cli.user_words.clone();
+    config.user_patterns = cli.user_patterns.clone();
+    config.tessdata_path = cli.tessdata.clone();
+    // The DPI and debug options alone preprocess with the default steps
+    if !cli.preprocess.is_empty() || cli.screen_dpi.is_some() || cli.target_dpi.is_some() || cli.preprocess_debug.is_some() {
+        let mut preprocess = PreprocessConfig::default().with_dpi(cli.screen_dpi, cli.target_dpi);
+        preprocess.steps = (!cli.preprocess.is_empty()).then(|| cli.preprocess.clone());
+        preprocess.debug_dir = cli.preprocess_debug.clone();
+        config = config.with_preprocess(preprocess);
+    }
+    config.with_min_confidence(cli.min_confidence, cli.min_line_confidence)
+}
+
+/// Formats of the frames on stdin.
+#[derive(Debug, Clone, Copy)]
+enum StdinFormat {
+    K21,
+    Y4m,
+    RawVideo,
+}
+
+impl std::str::FromStr for StdinFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "k21" => Ok(StdinFormat::K21),
+            "y4m" | "yuv4mpegpipe" => Ok(StdinFormat::Y4m),
+            "rawvideo" | "raw" => Ok(StdinFormat::RawVideo),
+            _ => Err(anyhow::anyhow!("Unknown stdin format {:?}", s)),
+        }
+    }
+}
+
+/// Reads frames from stdin in any of the supported formats.
+enum StdinDecoder<R> {
+    K21(FrameDecoder<R>),
+    Y4m(Y4mDecoder<R>),
+    RawVideo(RawVideoDecoder<R>),
+}
+
+impl<R: AsyncRead + Unpin> StdinDecoder<R> {
+    fn new(reader: R, cli: &Cli) -> anyhow::Result<Self> {
+        Ok(match cli.stdin_format {
+            StdinFormat::Y4m => StdinDecoder::Y4m(Y4mDecoder::new(reader)),
+            StdinFormat::RawVideo => StdinDecoder::RawVideo(RawVideoDecoder::new(
+                reader,
+                cli.width.unwrap_or(0),
+                cli.height.unwrap_or(0),
+                cli.pix_fmt,
+            )?),
+            StdinFormat::K21 => StdinDecoder::K21(FrameDecoder::new(reader)),
+        })
+    }
+
+    async fn read_frame(&mut self) -> anyhow::Result<Option<CapturedFrame>> {
+        match self {
+            StdinDecoder::K21(decoder) => decoder.read_frame().await,
+            StdinDecoder::Y4m(decoder) => decoder.read_frame().await,
+            StdinDecoder::RawVideo(decoder) => decoder.read_frame().await,
+        }
+    }
 }
 
 #[tokio::main]
@@ -48,11 +190,17 @@ async fn main() {
     })
     .expect("Error setting Ctrl-C handler");
 
+    let ocr_config = ocr_config(&cli);
+    if let Err(e) = ocr_config.validate() {
+        log::error!("Invalid OCR config: {}", e);
+        std::process::exit(1);
+    }
+
     if cli.image.is_some() {
         let path = cli.image.unwrap();
         let image = image::open(&path);
         if let Ok(image) = image {
-            let ocr_res = process_ocr(&image, &OcrConfig::default()).await;
+            let ocr_res = process_ocr(&image, &ocr_config).await;
             if let Ok(text) = ocr_res {
                 log::info!("OCR result: {}", text);
             } else {
@@ -66,7 +214,8 @@ async fn main() {
         let start_time = std::time::Instant::now();
 
         let state = Arc::new(Mutex::new(ImageDataCollection::new()));
-        process_mp4_buffer_path(&cli.mp4.unwrap(), &ProcessorConfig::default(), state)
+        let config = ProcessorConfig { ocr_config: Some(ocr_config), ..ProcessorConfig::default() };
+        process_mp4_buffer_path(&cli.mp4.unwrap(), &config, state)
         .await
         .unwrap();
 
@@ -74,70 +223,60 @@ async fn main() {
         log::info!("Total characters: {}", char_counter.load(Ordering::SeqCst));
         log::info!("Time taken: {:.2?}", elapsed);
     } else if cli.stdin {
-        let mut stdin: BufReader<io::Stdin> = BufReader::new(io::stdin());
-        let mut previous_image: Option<RgbImage> = None;
-        
-        loop {
-            // Read the frame number (assume it's a u64, 8 bytes)
-            let mut frame_number_bytes = [0u8; 8];
-            if stdin.read_exact(&mut frame_number_bytes).await.is_err() {
-                break; // Exit on EOF
+        let mut decoder = match StdinDecoder::new(BufReader::new(io::stdin()), &cli) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                log::error!("Failed to read stdin: {}", e);
+                std::process::exit(1);
             }
-            let frame_number = u64::from_le_bytes(frame_number_bytes); // Convert bytes to u32
+        };
+        // Frames of several monitors are interleaved, each is compared to the previous one of its stream
+        let mut previous_images: HashMap<Option<String>, RgbImage> = HashMap::new();
 
-            // read width and height
-            let mut width_bytes = [0u8; 4];
-            if stdin.read_exact(&mut width_bytes).await.is_err() {
-                break;
-            }
-            let width = u32::from_le_bytes(width_bytes);
+        loop {
+            let frame = match decoder.read_frame().await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break, // Exit on EOF
+                Err(e) => {
+                    log::error!("Failed to read frame: {}", e);
+                    break;
+                }
+            };
 
-            let mut height_bytes = [0u8; 4];
-            if stdin.read_exact(&mut height_bytes).await.is_err() {
-                break;
-            }
-            let height = u32::from_le_bytes(height_bytes);
+            log::info!(
+                "Received frame {} of monitor {:?} captured at {}",
+                frame.frame_number,
+                frame.monitor_id,
+                frame.captured_at
+            );
 
-            // Read the data size (assume it's a usize, 8 bytes)
-            let mut size_bytes = [0u8; 8];
-            if stdin.read_exact(&mut size_bytes).await.is_err() {
-                break;
-            }
-            let data_size = usize::from_le_bytes(size_bytes);
+            let stream_name = frame.stream_name();
+            let rgb_image = frame.image.to_rgb8();
 
-            // Read the binary frame data (Vec<u8>)
-            let mut buffer = vec![0u8; data_size];
-            if stdin.read_exact(&mut buffer[..data_size]).await.is_err() {
-                break;
-            }
+            // Check image difference if we have a previous frame
+            let should_process = if let Some(prev_img) = previous_images.get(&stream_name) {
+                let diff = images_differ_rgb(&rgb_image, prev_img, 0.05);
+                log::debug!("Images differ: {}", diff);
+                diff
+            } else {
+                true // Always process first frame
+            };
 
-            log::info!("Received frame {}, len {}", frame_number, data_size);
-
-            let rgb_image = image::RgbImage::from_raw(width, height, buffer);
-            if let Some(rgb_image) = rgb_image {
-                let image = DynamicImage::ImageRgb8(rgb_image.clone());
-                
-                // Check image difference if we have a previous frame
-                let should_process = if let Some(prev_img) = &previous_image {
-                    let diff = images_differ_rgb(&rgb_image, prev_img, 0.05);
-                    log::debug!("Images differ: {}", diff);
-                    diff
+            if should_process {
+                let ocr_res = process_ocr(&frame.image, &ocr_config).await;
+                if let Ok(text) = ocr_res {
+                    log::info!("OCR result: {}", text);
                 } else {
-                    true // Always process first frame
-                };
-
-                if should_process {
-                    let ocr_res = process_ocr(&image, &OcrConfig::default()).await;
-                    if let Ok(text) = ocr_res {
-                        log::info!("OCR result: {}", text);
-                    } else {
-                        log::error!("Failed to process OCR: {}", ocr_res.unwrap_err());
-                    }
+                    log::error!("Failed to process OCR: {}", ocr_res.unwrap_err());
                 }
+            }
 
-                previous_image = Some(rgb_image);
-            } else {
-                log::error!("Failed to open image");
+            previous_images.insert(stream_name, rgb_image);
+        }
+
+        if let StdinDecoder::K21(decoder) = &decoder {
+            if decoder.skipped_bytes() > 0 {
+                log::warn!("Skipped {} bytes of corrupt input", decoder.skipped_bytes());
             }
         }
     }
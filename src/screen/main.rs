@@ -1,6 +1,11 @@
 use clap::Parser;
 use k21::logger::init_logger_exe;
-use k21::capture::{capture_with_stdout, ScreenCaptureConfig};
+use k21::capture::{
+    capture_with_stdout, AdaptiveFps, BackpressurePolicy, CaptureRegion, CaptureSchedule, CaptureTarget, EncoderSettings,
+    FrameEncoding, MonitorSelection, OddSizePolicy, RateControl, RedactionConfig, RedactionStyle, ResizeFilter,
+    RetentionPolicy, ScheduleWindow, ScreenCaptureConfig, ScreenshotFormat, SensitivePattern, StdoutFormat,
+    WindowSelector,
+};
 
 #[derive(Parser)]
 #[command(version, about = "A CLI tool to handle screen refresh rates", long_about = None)]
@@ -11,6 +16,20 @@ struct Cli {
         default_value_t = 1.0
     )]
     fps: f32,
+    #[arg(
+        long,
+        help = "Seconds between two captures, e.g. 10 for one frame every 10 seconds, instead of --fps",
+        value_parser
+    )]
+    interval: Option<f32>,
+    #[arg(long, help = "Stop after this many captures", value_parser)]
+    count: Option<u64>,
+    #[arg(
+        long,
+        help = "How long to capture in whole seconds, e.g. 10s or 2h, captures until stopped if not set",
+        value_parser = parse_whole_seconds
+    )]
+    duration: Option<std::time::Duration>,
     #[arg(
         long,
         help = "Duration of each video chunk in seconds",
@@ -23,6 +42,20 @@ struct Cli {
         default_value_t = false
     )]
     stdout: bool,
+    #[arg(
+        long,
+        help = "Encoding of frames sent to stdout: raw, png, jpeg or zstd",
+        default_value = "raw",
+        value_parser
+    )]
+    stdout_encoding: FrameEncoding,
+    #[arg(
+        long,
+        help = "Format of frames sent to stdout: k21 for k21-processor or y4m for ffmpeg/gstreamer",
+        default_value = "k21",
+        value_parser
+    )]
+    stdout_format: StdoutFormat,
     #[arg(
         long,
         help = "Directory path to save screenshots",
@@ -35,6 +68,186 @@ struct Cli {
         value_parser
     )]
     save_video_to: Option<String>,
+    #[arg(
+        long,
+        help = "Monitors to capture: primary, all or a comma separated list of monitor ids/names",
+        default_value = "primary"
+    )]
+    monitors: String,
+    #[arg(
+        long,
+        help = "Capture windows whose app name matches this regex instead of monitors",
+        value_parser
+    )]
+    window_app: Option<String>,
+    #[arg(
+        long,
+        help = "Capture windows whose title matches this regex instead of monitors",
+        value_parser
+    )]
+    window_title: Option<String>,
+    #[arg(
+        long,
+        help = "Number of frames between two video keyframes",
+        value_parser
+    )]
+    keyframe_interval: Option<u32>,
+    #[arg(
+        long,
+        help = "Target video bitrate in bits per second",
+        value_parser
+    )]
+    bitrate: Option<u32>,
+    #[arg(
+        long,
+        help = "Video rate control: quality, bitrate, buffer, timestamp or off",
+        value_parser
+    )]
+    rate_control: Option<RateControl>,
+    #[arg(
+        long,
+        help = "Maximum video quantizer (0-51), lower means better quality and bigger files",
+        value_parser
+    )]
+    max_qp: Option<u8>,
+    #[arg(
+        long,
+        help = "How frames of an odd width or height are recorded: pad repeats the last column/row, crop drops it",
+        default_value = "pad",
+        value_parser
+    )]
+    odd_size: OddSizePolicy,
+    #[arg(
+        long,
+        help = "Write crash-safe fragmented MP4 with one fragment per this many frames",
+        value_parser
+    )]
+    fragment_frames: Option<u32>,
+    #[arg(
+        long,
+        help = "Delete the oldest recordings once the output directories exceed this many bytes",
+        value_parser
+    )]
+    max_bytes: Option<u64>,
+    #[arg(
+        long,
+        help = "Delete recordings older than this, e.g. 7d or 12h",
+        value_parser = parse_whole_seconds
+    )]
+    max_age: Option<std::time::Duration>,
+    #[arg(
+        long,
+        help = "Delete the oldest recordings once the output directories hold more files",
+        value_parser
+    )]
+    max_files: Option<usize>,
+    #[arg(
+        long,
+        help = "Keep one frame per minute of expired videos as PNG instead of deleting them",
+        default_value_t = false
+    )]
+    downsample: bool,
+    #[arg(
+        long,
+        help = "Screenshot format: png, jpeg, webp or webp-lossless",
+        default_value = "png",
+        value_parser
+    )]
+    format: ScreenshotFormat,
+    #[arg(
+        long,
+        help = "Compression quality (1-100) of jpeg and webp screenshots",
+        value_parser
+    )]
+    quality: Option<u8>,
+    #[arg(
+        long,
+        help = "Scale captured frames to this percentage (1-100) of the screen size",
+        value_parser
+    )]
+    scale: Option<u8>,
+    #[arg(
+        long,
+        help = "Filter for scaling frames: nearest, triangle, catmullrom, gaussian or lanczos3",
+        value_parser
+    )]
+    resize_filter: Option<ResizeFilter>,
+    #[arg(
+        long,
+        help = "Adapt the capture rate to screen activity between --min-fps and --max-fps instead of using --fps",
+        default_value_t = false
+    )]
+    adaptive: bool,
+    #[arg(long, help = "Idle capture rate of adaptive capture", value_parser = parse_positive_fps)]
+    min_fps: Option<f32>,
+    #[arg(long, help = "Capture rate of adaptive capture while the screen changes", value_parser = parse_positive_fps)]
+    max_fps: Option<f32>,
+    #[arg(
+        long,
+        help = "Share of changed pixels (0-1) that counts as a screen change in adaptive capture",
+        value_parser
+    )]
+    change_threshold: Option<f32>,
+    #[arg(
+        long = "region",
+        help = "Only capture this region as [name:]x,y,width,height in pixels or fractions like 0.5, can be repeated",
+        value_parser
+    )]
+    regions: Vec<CaptureRegion>,
+    #[arg(
+        long = "redact-region",
+        help = "Black out or blur this region as [name:]x,y,width,height before storing anything, can be repeated",
+        value_parser
+    )]
+    redact_regions: Vec<CaptureRegion>,
+    #[arg(long, help = "Redact windows whose app name matches this regex", value_parser)]
+    redact_window_app: Option<String>,
+    #[arg(long, help = "Redact windows whose title matches this regex", value_parser)]
+    redact_window_title: Option<String>,
+    #[arg(
+        long = "redact-pattern",
+        help = "Redact text OCR finds on screen: password, card-number, api-key or a regex, can be repeated",
+        value_parser
+    )]
+    redact_patterns: Vec<String>,
+    #[arg(long, help = "How to redact: black or blur", default_value = "black", value_parser)]
+    redact_style: RedactionStyle,
+    #[arg(long, help = "JSON lines file to record every redaction in", value_parser)]
+    redaction_audit: Option<String>,
+    #[arg(long, help = "Frames recording may fall behind capturing before --backpressure applies", value_parser)]
+    frame_queue: Option<usize>,
+    #[arg(
+        long,
+        help = "When recording falls behind: block, drop-oldest, drop-newest or coalesce",
+        default_value = "block",
+        value_parser
+    )]
+    backpressure: BackpressurePolicy,
+    #[arg(
+        long = "schedule",
+        help = "Only capture within a time window like \"mon-fri 09:00-17:00\" or a cron expression like \"* 9-16 * * 1-5\", can be repeated",
+        value_parser
+    )]
+    schedules: Vec<ScheduleWindow>,
+    #[arg(long, help = "Time zone of --schedule like Europe/Berlin, the system time zone if not set", value_parser)]
+    schedule_timezone: Option<String>,
+}
+
+/// Durations are passed on in seconds, so fractions of a second would be cut off.
+fn parse_whole_seconds(s: &str) -> Result<std::time::Duration, String> {
+    let duration = humantime::parse_duration(s).map_err(|e| e.to_string())?;
+    if duration.subsec_nanos() != 0 || duration.is_zero() {
+        return Err(format!("{} is not a positive whole number of seconds", s));
+    }
+    Ok(duration)
+}
+
+fn parse_positive_fps(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(fps) if fps.is_finite() && fps > 0.0 => Ok(fps),
+        Ok(fps) => Err(format!("{} is not a positive rate", fps)),
+        Err(e) => Err(e.to_string()),
+    }
 }
 
 #[tokio::main]
@@ -42,6 +255,12 @@ async fn main() {
     init_logger_exe();
 
     let cli = Cli::parse();
+    if let (Some(min_fps), Some(max_fps)) = (cli.min_fps, cli.max_fps) {
+        if max_fps < min_fps {
+            log::error!("--max-fps {} is below --min-fps {}", max_fps, min_fps);
+            std::process::exit(1);
+        }
+    }
 
     // init tokio runtime
     let rt = tokio::runtime::Builder::new_multi_thread()
@@ -50,12 +269,63 @@ async fn main() {
         .unwrap();
     let _ = rt.enter();
 
+    let target = if cli.window_app.is_some() || cli.window_title.is_some() {
+        CaptureTarget::Windows(WindowSelector::new(cli.window_app, cli.window_title))
+    } else {
+        CaptureTarget::Monitors
+    };
+
+    let redact_windows = if cli.redact_window_app.is_some() || cli.redact_window_title.is_some() {
+        vec![WindowSelector::new(cli.redact_window_app, cli.redact_window_title)]
+    } else {
+        Vec::new()
+    };
+    let redaction = RedactionConfig {
+        style: Some(cli.redact_style),
+        regions: Some(cli.redact_regions),
+        windows: Some(redact_windows),
+        patterns: Some(cli.redact_patterns.into_iter().map(SensitivePattern::from).collect()),
+        audit_log: cli.redaction_audit,
+        ocr: None,
+    };
+
     let config = ScreenCaptureConfig {
         fps: Some(cli.fps),
+        interval: cli.interval,
+        count: cli.count,
         save_screenshot_to: cli.save_screenshot_to,
         save_video_to: cli.save_video_to,
-        duration: Some(0),
+        duration: Some(cli.duration.map_or(0, |duration| duration.as_secs())),
         video_chunk_duration: Some(cli.video_chunk_duration),
+        monitors: Some(MonitorSelection::from(cli.monitors)),
+        target: Some(target),
+        encoder: Some(EncoderSettings::new(
+            cli.keyframe_interval,
+            cli.bitrate,
+            cli.rate_control,
+            cli.max_qp,
+        ).with_odd_size(cli.odd_size)),
+        fragment_frames: cli.fragment_frames,
+        quality: cli.scale,
+        screenshot_format: Some(cli.format),
+        compression_quality: cli.quality,
+        resize_filter: cli.resize_filter,
+        adaptive_fps: cli
+            .adaptive
+            .then(|| AdaptiveFps::new(cli.min_fps, cli.max_fps, cli.change_threshold)),
+        regions: (!cli.regions.is_empty()).then_some(cli.regions),
+        redaction: Some(redaction),
+        frame_queue_capacity: cli.frame_queue,
+        recorder_backpressure: Some(cli.backpressure),
+        stdout_encoding: Some(cli.stdout_encoding),
+        stdout_format: Some(cli.stdout_format),
+        schedule: Some(CaptureSchedule::new(cli.schedule_timezone, cli.schedules)),
+        retention: Some(RetentionPolicy::new(
+            cli.max_bytes,
+            cli.max_age.map(|max_age| max_age.as_secs()),
+            cli.max_files,
+            Some(cli.downsample),
+        )),
         ..Default::default()
     };
 
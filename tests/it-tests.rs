@@ -1,4 +1,8 @@
-use k21::capture::{self, ScreenCaptureConfig};
+use k21::capture::{
+    self, enforce_retention, BackpressurePolicy, CaptureRegion, CaptureState, CaptureTarget, CapturedFrame,
+    ChunkManifest, FrameDecoder, FrameEncoder, FrameEncoding, FrameFanout, FrameSource, MonitorSelection, Mp4FileSource, RedactionConfig, RedactionReason,
+    Redactor, RetentionPolicy, ScreenCaptureConfig, ScreenshotFormat, WindowSelector,
+};
 use std::time::Duration;
 
 #[cfg(test)]
@@ -42,6 +46,755 @@ mod capture_tests {
         assert!(result.is_ok(), "Capture should complete within timeout");
         assert!(result.unwrap().is_ok(), "Screen capture should succeed");
     }
+
+    #[test]
+    fn test_monitor_selection_from_str() {
+        assert_eq!(MonitorSelection::from("all"), MonitorSelection::All);
+        assert_eq!(MonitorSelection::from("Primary"), MonitorSelection::Primary);
+
+        let selection = MonitorSelection::from("1, DP-2");
+        assert_eq!(selection, MonitorSelection::List(vec!["1".to_string(), "DP-2".to_string()]));
+        assert!(selection.matches(1, "eDP-1", true));
+        assert!(selection.matches(7, "DP-2", false));
+        assert!(!selection.matches(3, "HDMI-1", false));
+    }
+
+    #[test]
+    fn test_window_selector_matching() {
+        let compile = |app_name: Option<&str>, title: Option<&str>| {
+            WindowSelector::new(app_name.map(str::to_string), title.map(str::to_string)).compile()
+        };
+
+        let matcher = compile(Some("(?i)firefox"), None).expect("Failed to compile selector");
+        assert!(matcher.matches("Firefox", "Inbox"));
+        assert!(!matcher.matches("Slack", "Inbox"));
+
+        let matcher = compile(None, Some("^Inbox")).expect("Failed to compile selector");
+        assert!(matcher.matches("Thunderbird", "Inbox - Mail"));
+        assert!(!matcher.matches("Thunderbird", "Drafts - Inbox"));
+
+        // Both patterns have to match
+        let matcher = compile(Some("Code"), Some("k21")).expect("Failed to compile selector");
+        assert!(matcher.matches("Code", "main.rs - k21"));
+        assert!(!matcher.matches("Code", "main.rs - other"));
+        assert!(!matcher.matches("Terminal", "k21"));
+
+        assert!(compile(Some("[unclosed"), None).is_err());
+    }
+
+    /// Empty directory in the system temp directory, left over from an earlier run or not.
+    fn fresh_output_dir(name: &str) -> std::path::PathBuf {
+        let output_dir = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&output_dir);
+        std::fs::create_dir_all(&output_dir).expect("Failed to create output directory");
+        output_dir
+    }
+
+    /// Finished video chunks in `output_dir`.
+    fn list_videos(output_dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+        std::fs::read_dir(output_dir)
+            .expect("Failed to read output directory")
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "mp4"))
+            .collect()
+    }
+
+    async fn capture_synthetic_video(output_dir: &std::path::Path, fragment_frames: Option<u32>) -> Vec<std::path::PathBuf> {
+        let config = ScreenCaptureConfig {
+            fps: Some(5.0),
+            duration: Some(1),
+            save_video_to: Some(output_dir.to_string_lossy().to_string()),
+            target: Some(CaptureTarget::Synthetic { width: 64, height: 48 }),
+            fragment_frames,
+            ..Default::default()
+        };
+
+        let result = capture::capture(config).await;
+        assert!(result.is_ok(), "Synthetic capture should succeed without a display");
+
+        list_videos(output_dir)
+    }
+
+    fn count_decoded_frames(videos: &[std::path::PathBuf]) -> u64 {
+        let mut decoded = 0;
+        for video in videos {
+            let mut source = Mp4FileSource::open(video).expect("Failed to open written chunk");
+            while let Some(frames) = source.next_frames(decoded + 1).expect("Failed to decode frame") {
+                decoded += frames.len() as u64;
+            }
+        }
+        decoded
+    }
+
+    #[test]
+    fn test_screenshot_format_from_str() {
+        assert_eq!("JPG".parse::<ScreenshotFormat>().unwrap(), ScreenshotFormat::Jpeg);
+        assert_eq!("webp-lossless".parse::<ScreenshotFormat>().unwrap(), ScreenshotFormat::WebPLossless);
+        assert!("bmp".parse::<ScreenshotFormat>().is_err());
+        assert_eq!(ScreenshotFormat::WebP.extension(), "webp");
+    }
+
+    #[test]
+    fn test_interval_capture_frame_count() {
+        let config = ScreenCaptureConfig {
+            interval: Some(10.0),
+            duration: Some(25),
+            ..Default::default()
+        };
+        assert_eq!(config.get_fps(), 0.1);
+        assert_eq!(config.get_interval(), Duration::from_secs(10));
+        assert_eq!(config.get_frames_to_capture(), Some(3));
+
+        let config = ScreenCaptureConfig {
+            fps: Some(0.3),
+            duration: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(config.get_frames_to_capture(), Some(3));
+
+        let config = ScreenCaptureConfig {
+            fps: Some(0.0),
+            duration: Some(0),
+            count: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(config.get_fps(), 1.0);
+        assert_eq!(config.get_frames_to_capture(), Some(4));
+    }
+
+    #[test]
+    fn test_crop_frame_to_regions() {
+        let frame = CapturedFrame::new(1, image::DynamicImage::new_rgb8(200, 100)).with_monitor_id(2);
+
+        let region: CaptureRegion = "pane:0.5,0,0.5,1".parse().expect("Failed to parse region");
+        let cropped = frame.crop(0, &region).expect("Region should lie inside the frame");
+        assert_eq!((cropped.image.width(), cropped.image.height()), (100, 100));
+        assert_eq!(cropped.stream_name().as_deref(), Some("monitor2-region0"));
+
+        let info = cropped.region.expect("Cropped frame should know its region");
+        assert_eq!(info.name.as_deref(), Some("pane"));
+        assert_eq!(info.to_frame_coordinates(0.5, 0.5), (0.75, 0.5));
+
+        // Pixel regions are clamped to the frame
+        let region: CaptureRegion = "150,50,100,100".parse().expect("Failed to parse region");
+        let cropped = frame.crop(1, &region).expect("Region should overlap the frame");
+        assert_eq!((cropped.image.width(), cropped.image.height()), (50, 50));
+
+        assert!(frame.crop(2, &CaptureRegion::pixels(300, 0, 10, 10)).is_none());
+        assert!("1,2,3".parse::<CaptureRegion>().is_err());
+
+        // Values of only 0 and 1 are fractions, a 1x1 pixel region is never meant
+        let region: CaptureRegion = "0,0,1,1".parse().expect("Failed to parse region");
+        assert_eq!(region, CaptureRegion::normalized(0.0, 0.0, 1.0, 1.0));
+        let cropped = frame.crop(3, &region).expect("Region should cover the frame");
+        assert_eq!((cropped.image.width(), cropped.image.height()), (200, 100));
+        assert_eq!("0,0,1,10".parse::<CaptureRegion>().unwrap(), CaptureRegion::pixels(0, 0, 1, 10));
+    }
+
+    #[test]
+    fn test_redact_region_with_audit() {
+        let audit_log = std::env::temp_dir().join("k21-it-redaction-audit.jsonl");
+        let _ = std::fs::remove_file(&audit_log);
+
+        let redactor = Redactor::new(&RedactionConfig {
+            regions: Some(vec![CaptureRegion::pixels(10, 10, 20, 20).with_name("secret")]),
+            audit_log: Some(audit_log.to_string_lossy().to_string()),
+            ..Default::default()
+        })
+        .expect("Failed to set up redaction");
+
+        let white = image::RgbImage::from_pixel(64, 48, image::Rgb([255, 255, 255]));
+        let mut frame = CapturedFrame::new(7, image::DynamicImage::ImageRgb8(white));
+        let record = redactor.redact(&mut frame).expect("Failed to redact frame");
+
+        let image = frame.image.to_rgb8();
+        assert_eq!(image.get_pixel(15, 15).0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(5, 5).0, [255, 255, 255]);
+        assert_eq!(record.redactions.len(), 1);
+        assert_eq!(
+            record.redactions[0].reason,
+            RedactionReason::Region { index: 0, name: Some("secret".to_string()) }
+        );
+
+        let audit = std::fs::read_to_string(&audit_log).expect("Audit log should be written");
+        assert_eq!(audit.lines().count(), 1);
+        assert!(audit.contains("\"frame_number\":7"));
+
+        let _ = std::fs::remove_file(&audit_log);
+    }
+
+    #[tokio::test]
+    async fn test_frame_fanout_policies() {
+        let frame = |frame_number| CapturedFrame::new(frame_number, image::DynamicImage::new_rgb8(4, 4));
+
+        let mut fanout = FrameFanout::new();
+        let mut blocking = fanout.subscribe("blocking", BackpressurePolicy::Block, 2);
+        let mut newest = fanout.subscribe("newest", BackpressurePolicy::DropNewest, 2);
+        let mut latest = fanout.subscribe("latest", BackpressurePolicy::CoalesceLatest, 2);
+        let stats = fanout.stats();
+
+        let consumer = tokio::spawn(async move {
+            let mut frame_numbers = Vec::new();
+            while let Some(frame) = blocking.recv().await {
+                frame_numbers.push(frame.frame_number);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            frame_numbers
+        });
+        for frame_number in 1..=5 {
+            fanout.send(frame(frame_number)).await.expect("Failed to send frame");
+        }
+        drop(fanout);
+
+        // Blocking consumers get every frame, also the ones queued when the fanout closed
+        assert_eq!(consumer.await.expect("Consumer failed"), vec![1, 2, 3, 4, 5]);
+
+        let mut received = Vec::new();
+        while let Some(frame) = newest.recv().await {
+            received.push(frame.frame_number);
+        }
+        assert_eq!(received, vec![1, 2]);
+
+        let mut received = Vec::new();
+        while let Some(frame) = latest.recv().await {
+            received.push(frame.frame_number);
+        }
+        assert_eq!(received, vec![5]);
+
+        let consumers = stats.consumers();
+        assert_eq!(consumers[0].dropped, 0);
+        assert!(consumers[0].lagged > 0);
+        assert_eq!((consumers[1].delivered, consumers[1].dropped), (2, 3));
+        assert_eq!((consumers[2].delivered, consumers[2].dropped), (1, 4));
+    }
+
+    #[tokio::test]
+    async fn test_frame_stream_round_trip() {
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(16, 8, |x, y| {
+            image::Rgb([(x * 16) as u8, (y * 32) as u8, 128])
+        }));
+        let frame = CapturedFrame::new(7, image.clone()).with_monitor_id(2);
+
+        for encoding in [FrameEncoding::Raw, FrameEncoding::Png, FrameEncoding::Zstd] {
+            let mut encoder = FrameEncoder::new(Vec::new(), encoding);
+            encoder.write_frame(&frame).await.expect("Failed to encode frame");
+            encoder.write_frame(&frame).await.expect("Failed to encode frame");
+            let mut stream = encoder.into_inner();
+
+            // Garbage within the first frame's header, the decoder has to find the second frame
+            stream[12] ^= 0xFF;
+
+            let mut decoder = FrameDecoder::new(stream.as_slice());
+            let decoded = decoder
+                .read_frame()
+                .await
+                .expect("Failed to decode frame")
+                .expect("Stream should have a frame");
+            assert_eq!(decoded.frame_number, 7);
+            assert_eq!(decoded.monitor_id, Some(2));
+            assert_eq!(decoded.captured_at.timestamp_micros(), frame.captured_at.timestamp_micros());
+            assert_eq!(decoded.image.to_rgb8(), image.to_rgb8(), "{:?} should be lossless", encoding);
+            assert!(decoder.skipped_bytes() > 0);
+            assert!(decoder.read_frame().await.expect("Failed to read end of stream").is_none());
+        }
+
+        // Streams without a header, like the old raw format, are rejected
+        let mut decoder = FrameDecoder::new(&[0u8; 32][..]);
+        assert!(decoder.read_frame().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_y4m_and_rawvideo_interop() {
+        let color = image::Rgb([200u8, 40, 90]);
+        let frame = CapturedFrame::new(1, image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(15, 9, color)));
+
+        let mut encoder = capture::Y4mEncoder::new(Vec::new(), 0.5);
+        encoder.write_frame(&frame).await.expect("Failed to write Y4M frame");
+        encoder.write_frame(&frame).await.expect("Failed to write Y4M frame");
+        let odd_sized = CapturedFrame::new(3, image::DynamicImage::new_rgb8(16, 9));
+        assert!(encoder.write_frame(&odd_sized).await.is_err(), "Y4M streams can't change size");
+        let stream = encoder.into_inner();
+        assert!(stream.starts_with(b"YUV4MPEG2 W15 H9 F1:2 "));
+
+        let mut decoder = capture::Y4mDecoder::new(stream.as_slice());
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_frame().await.expect("Failed to read Y4M frame") {
+            frames.push(frame);
+        }
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].frame_number, 2);
+        assert_eq!(frames[1].captured_at - frames[0].captured_at, chrono::Duration::seconds(2));
+        for (decoded, expected) in frames[0].image.to_rgb8().pixels().next().unwrap().0.iter().zip(color.0) {
+            assert!(decoded.abs_diff(expected) <= 3, "YUV round trip is off: {} vs {}", decoded, expected);
+        }
+
+        // Headerless frames only go by the given size and pixel format
+        let bgr = [90u8, 40, 200].repeat(4 * 2 * 2);
+        let mut decoder = capture::RawVideoDecoder::new(bgr.as_slice(), 4, 2, capture::RawPixelFormat::Bgr24)
+            .expect("Failed to create raw video decoder");
+        for frame_number in 1..=2 {
+            let frame = decoder.read_frame().await.expect("Failed to read raw frame").expect("Should have a frame");
+            assert_eq!(frame.frame_number, frame_number);
+            assert_eq!(frame.image.to_rgb8().get_pixel(3, 1), &color);
+        }
+        assert!(decoder.read_frame().await.expect("Failed to read end of stream").is_none());
+
+        assert_eq!("I420".parse::<capture::RawPixelFormat>().unwrap(), capture::RawPixelFormat::Yuv420p);
+        assert!("rgb48".parse::<capture::RawPixelFormat>().is_err());
+    }
+
+    #[test]
+    fn test_capture_schedule_windows() {
+        let utc = |s: &str| s.parse::<chrono::DateTime<chrono::Utc>>().expect("Invalid test time");
+        let window = |s: &str| s.parse::<capture::ScheduleWindow>().expect("Failed to parse schedule");
+
+        let office_hours = capture::CaptureSchedule::new(
+            Some("Europe/Berlin".to_string()),
+            vec![window("mon-fri 09:00-17:00")],
+        );
+        let schedule = capture::Schedule::new(&office_hours).expect("Failed to compile schedule");
+        // Monday 8 January 2024, Berlin is UTC+1
+        assert!(!schedule.is_active(utc("2024-01-08T07:59:00Z")));
+        assert!(schedule.is_active(utc("2024-01-08T08:00:00Z")));
+        assert!(!schedule.is_active(utc("2024-01-08T16:00:00Z")));
+        assert!(!schedule.is_active(utc("2024-01-06T10:00:00Z")), "Saturday is off");
+        // Friday evening waits for monday morning
+        assert_eq!(schedule.next_change(utc("2024-01-12T18:30:10Z")), Some(utc("2024-01-15T08:00:00Z")));
+
+        let cron = capture::CaptureSchedule::new(Some("UTC".to_string()), vec![window("*/30 22-23 * * sat,sun")]);
+        let schedule = capture::Schedule::new(&cron).expect("Failed to compile schedule");
+        assert!(schedule.is_active(utc("2024-01-06T22:30:00Z")));
+        assert!(!schedule.is_active(utc("2024-01-06T22:31:00Z")));
+        assert!(!schedule.is_active(utc("2024-01-08T22:30:00Z")));
+
+        // A stepped `*` is unrestricted, so both days have to match: odd days that are mondays
+        let stepped = capture::CaptureSchedule::new(Some("UTC".to_string()), vec![window("0 12 */2 * mon")]);
+        let schedule = capture::Schedule::new(&stepped).expect("Failed to compile schedule");
+        assert!(schedule.is_active(utc("2024-01-15T12:00:00Z")));
+        assert!(!schedule.is_active(utc("2024-01-08T12:00:00Z")));
+        assert!(!schedule.is_active(utc("2024-01-09T12:00:00Z")));
+
+        assert!("mon-fri 25:00-26:00".parse::<capture::ScheduleWindow>().is_err());
+        assert!("* * * * mon-xyz".parse::<capture::ScheduleWindow>().is_err());
+        let unknown_zone = capture::CaptureSchedule::new(Some("Mars/Olympus".to_string()), vec![window("09:00-17:00")]);
+        assert!(capture::Schedule::new(&unknown_zone).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_odd_sized_frames() {
+        let output_dir = fresh_output_dir("k21-it-odd-size");
+
+        let config = ScreenCaptureConfig {
+            fps: Some(5.0),
+            duration: Some(1),
+            save_video_to: Some(output_dir.to_string_lossy().to_string()),
+            target: Some(CaptureTarget::Synthetic { width: 65, height: 47 }),
+            ..Default::default()
+        };
+        capture::capture(config).await.expect("Synthetic capture should succeed");
+
+        let videos = list_videos(&output_dir);
+        assert_eq!(count_decoded_frames(&videos), 5);
+        let manifest = ChunkManifest::read_for(&videos[0])
+            .expect("Failed to read manifest")
+            .expect("Chunk should have a manifest");
+        assert_eq!((manifest.width, manifest.height), (65, 47));
+        assert_eq!((manifest.coded_width, manifest.coded_height), (Some(66), Some(48)));
+
+        // A chunk has one size, a frame of another size needs a new chunk
+        let mut recorder = capture::ScreenCapturer::new(Default::default(), 5.0, None);
+        recorder.start(&output_dir.join("resized.mp4"));
+        let now = chrono::Local::now();
+        recorder.frame(&image::DynamicImage::new_rgb8(33, 20), now).expect("Failed to encode frame");
+        assert_eq!(recorder.coded_size(), Some((34, 20)));
+        assert!(recorder.frame(&image::DynamicImage::new_rgb8(40, 20), now).is_err());
+        recorder.save().expect("Failed to save chunk");
+        assert_eq!(recorder.frame_size(), None);
+
+        let _ = std::fs::remove_dir_all(&output_dir);
+    }
+
+    #[tokio::test]
+    async fn test_capture_synthetic_source_to_video() {
+        let output_dir = fresh_output_dir("k21-it-synthetic-capture");
+
+        let started = chrono::Local::now();
+        let videos = capture_synthetic_video(&output_dir, None).await;
+        let finished = chrono::Local::now();
+        assert!(!videos.is_empty(), "Should have written a video chunk");
+
+        // Frames read back carry the time they were captured, not the time they were read
+        let mut source = Mp4FileSource::open(&videos[0]).expect("Failed to open written chunk");
+        let first = source.next_frames(1).expect("Failed to decode frame").expect("Chunk should have frames");
+        let captured_at = first[0].captured_at;
+        assert!(captured_at >= started - chrono::Duration::milliseconds(1) && captured_at <= finished);
+
+        // Every captured frame has to be readable back from the finalized chunks
+        let decoded = count_decoded_frames(&videos);
+        assert_eq!(decoded, 5, "Decoded {} frames from 5 captured", decoded);
+
+        // The manifest next to the chunk describes exactly the frames in it
+        let manifest = ChunkManifest::read_for(&videos[0])
+            .expect("Failed to read manifest")
+            .expect("Chunk should have a manifest");
+        let video = std::fs::read(&videos[0]).expect("Failed to read chunk");
+        assert!(manifest.matches(&video));
+        assert_eq!(manifest.frames.len() as u64, decoded);
+        assert_eq!((manifest.width, manifest.height), (64, 48));
+
+        let _ = std::fs::remove_dir_all(&output_dir);
+    }
+
+    #[tokio::test]
+    async fn test_fragmented_video_recovery() {
+        let output_dir = fresh_output_dir("k21-it-fragmented-capture");
+
+        let videos = capture_synthetic_video(&output_dir, Some(2)).await;
+        assert!(count_decoded_frames(&videos) > 0, "Fragmented chunk should be readable");
+
+        // Pretend the recorder was killed in the middle of the last fragment
+        let video = &videos[0];
+        let bytes = std::fs::read(video).expect("Failed to read chunk");
+        let part = output_dir.join("killed.mp4.part");
+        std::fs::write(&part, &bytes[..bytes.len() - 3]).expect("Failed to write partial chunk");
+        std::fs::remove_file(video).expect("Failed to remove chunk");
+
+        // The next run finalizes the leftover before recording
+        let videos = capture_synthetic_video(&output_dir, Some(2)).await;
+        assert!(!part.exists(), "Partial chunk should have been recovered");
+        let recovered = output_dir.join("killed.mp4");
+        assert!(videos.contains(&recovered));
+        assert!(count_decoded_frames(&[recovered]) > 0, "Recovered chunk should be readable");
+
+        let _ = std::fs::remove_dir_all(&output_dir);
+    }
+
+    #[test]
+    fn test_retention_removes_oldest_first() {
+        let output_dir = fresh_output_dir("k21-it-retention");
+
+        let now = std::time::SystemTime::now();
+        for i in 0..4u64 {
+            let path = output_dir.join(format!("screenshot-{}.png", i));
+            std::fs::write(&path, vec![0u8; 100]).expect("Failed to write file");
+            let file = std::fs::File::options().write(true).open(&path).expect("Failed to open file");
+            file.set_modified(now - Duration::from_secs(3600 * (4 - i))).expect("Failed to set mtime");
+        }
+        // Files the policy doesn't own are left alone
+        std::fs::write(output_dir.join("notes.txt"), "keep").expect("Failed to write file");
+        // Downsampled frames of expired videos outlive the maximum age
+        let downsampled_dir = output_dir.join(capture::DOWNSAMPLED_DIR);
+        std::fs::create_dir_all(&downsampled_dir).expect("Failed to create downsampled directory");
+        let downsampled = downsampled_dir.join("output-0-0.png");
+        std::fs::write(&downsampled, vec![0u8; 10]).expect("Failed to write file");
+        let file = std::fs::File::options().write(true).open(&downsampled).expect("Failed to open file");
+        file.set_modified(now - Duration::from_secs(3600 * 24)).expect("Failed to set mtime");
+
+        let policy = RetentionPolicy::new(None, Some(3 * 3600 + 1800), None, None);
+        let report = enforce_retention(&output_dir, &policy).expect("Failed to enforce retention");
+        assert_eq!(report.deleted_files, 1);
+        assert!(!output_dir.join("screenshot-0.png").exists());
+        assert!(downsampled.exists());
+
+        // Size and file limits do apply to downsampled frames, they are the oldest
+        let policy = RetentionPolicy::new(Some(250), None, Some(3), None);
+        let report = enforce_retention(&output_dir, &policy).expect("Failed to enforce retention");
+        assert_eq!(report.deleted_files, 2);
+        assert_eq!(report.deleted_bytes, 110);
+        assert!(!downsampled.exists());
+        assert!(!output_dir.join("screenshot-1.png").exists());
+        assert!(output_dir.join("screenshot-3.png").exists());
+
+        let policy = RetentionPolicy::new(None, Some(5400), None, None);
+        let report = enforce_retention(&output_dir, &policy).expect("Failed to enforce retention");
+        assert_eq!(report.deleted_files, 1);
+        assert!(!output_dir.join("screenshot-2.png").exists());
+        assert!(output_dir.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn test_retention_downsamples_expired_videos() {
+        let output_dir = fresh_output_dir("k21-it-retention-downsample");
+
+        let expired = std::time::SystemTime::now() - Duration::from_secs(7200);
+        let video = output_dir.join("output-0.mp4");
+        std::fs::copy("tests/output-0.mp4", &video).expect("Failed to copy video");
+        let corrupt = output_dir.join("output-1.mp4");
+        std::fs::write(&corrupt, vec![0u8; 100]).expect("Failed to write file");
+        for path in [&video, &corrupt] {
+            let file = std::fs::File::options().write(true).open(path).expect("Failed to open file");
+            file.set_modified(expired).expect("Failed to set mtime");
+        }
+
+        let policy = RetentionPolicy::new(None, Some(3600), None, Some(true));
+        let report = enforce_retention(&output_dir, &policy).expect("Failed to enforce retention");
+        assert_eq!(report.downsampled_videos, 1);
+        assert_eq!(report.failed_downsamples, 1);
+        assert_eq!(report.deleted_files, 1);
+        assert!(!video.exists());
+        // A video that fails to downsample is kept for the next run
+        assert!(corrupt.exists());
+
+        let frames = std::fs::read_dir(output_dir.join(capture::DOWNSAMPLED_DIR))
+            .expect("Failed to read downsampled directory")
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "png"))
+            .count();
+        assert!(frames > 0, "Expired video should leave downsampled frames");
+
+        let _ = std::fs::remove_dir_all(&output_dir);
+    }
+
+    /// Polls `condition` until it holds, failing after 10 seconds.
+    async fn wait_for(condition: impl Fn() -> bool) {
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(std::time::Instant::now() < deadline, "Timed out waiting for the capture");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_capture_handle_pause_resume_stop() {
+        let output_dir = fresh_output_dir("k21-it-capture-handle");
+
+        // Duration 0 captures until stopped
+        let config = ScreenCaptureConfig {
+            fps: Some(10.0),
+            duration: Some(0),
+            save_video_to: Some(output_dir.to_string_lossy().to_string()),
+            target: Some(CaptureTarget::Synthetic { width: 64, height: 48 }),
+            ..Default::default()
+        };
+
+        let handle = capture::start_capture(config).await.expect("Failed to start capture");
+        wait_for(|| handle.status().frames_captured >= 3).await;
+
+        handle.pause();
+        wait_for(|| handle.status().state == CaptureState::Paused).await;
+        let paused = handle.status();
+        assert!(paused.frames_captured >= 3, "Frames should be captured before pausing");
+
+        // Several frame intervals, the capture loop is waiting for the resume
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(handle.status().frames_captured, paused.frames_captured, "No frames while paused");
+
+        handle.resume();
+        assert_eq!(handle.status().state, CaptureState::Running);
+        wait_for(|| handle.status().frames_captured > paused.frames_captured).await;
+
+        handle.stop().await.expect("Failed to stop capture");
+
+        let videos = list_videos(&output_dir);
+        assert_eq!(videos.len(), 1, "Stopping should flush the final chunk");
+        assert!(count_decoded_frames(&videos) > 0);
+
+        let _ = std::fs::remove_dir_all(&output_dir);
+    }
+
+    #[test]
+    fn test_mp4_file_source() {
+        let test_file_path = std::env::current_dir()
+            .expect("Failed to get current directory")
+            .join("tests")
+            .join("output-0.mp4");
+
+        let mut source = Mp4FileSource::open(&test_file_path).expect("Failed to open MP4 source");
+        let frames = source
+            .next_frames(1)
+            .expect("Failed to decode frame")
+            .expect("MP4 should contain frames");
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].frame_number, 1);
+        assert!(frames[0].image.width() > 0);
+    }
+}
+
+mod ocr_tests {
+    use super::*;
+    use k21::image2text::{
+        preprocess, process_ocr, register_ocr_engine, BoundingBox, OcrConfig, OcrEngine, OcrLine, OcrModel, OcrResult,
+        OcrWord, PreprocessConfig, PreprocessStep,
+    };
+
+    fn word(text: &str, left: u32, confidence: Option<f32>) -> OcrWord {
+        OcrWord { text: text.to_string(), bounding_box: BoundingBox::new(left, 10, 40, 20, 100, 100), confidence }
+    }
+
+    #[test]
+    fn test_ocr_result_text_and_frame_coordinates() {
+        let words = vec![word("hello", 0, Some(0.9)), word("world", 50, Some(0.7))];
+        let bounding_box = BoundingBox::union(words.iter().map(|word| &word.bounding_box), 100, 100).unwrap();
+        let result = OcrResult::from_lines(100, 100, vec![OcrLine { bounding_box, words }]);
+
+        assert_eq!(result.words().count(), 2);
+        assert_eq!(result.text(), "hello world");
+        assert_eq!(result.to_text(true), "(0.00, 0.10) hello world");
+        let line = result.lines().next().unwrap();
+        assert_eq!((line.bounding_box.width, line.bounding_box.height), (90, 20));
+        assert!((line.confidence().unwrap() - 0.8).abs() < 1e-6);
+
+        // Text of the right half of a 200x100 frame
+        let frame = CapturedFrame::new(1, image::DynamicImage::new_rgb8(200, 100));
+        let region: CaptureRegion = "0.5,0,0.5,1".parse().expect("Failed to parse region");
+        let info = frame.crop(0, &region).and_then(|cropped| cropped.region).expect("Region should lie inside the frame");
+        let mapped = result.to_frame_coordinates(&info);
+        let first = mapped.words().next().unwrap();
+        assert_eq!((mapped.width, mapped.height), (200, 100));
+        assert_eq!((first.bounding_box.left, first.bounding_box.top), (100, 10));
+        assert_eq!(mapped.to_text(true), "(0.50, 0.10) hello world");
+
+        // Same text read from the region scaled down to 50% quality
+        let scaled = OcrResult::from_lines(50, 50, vec![OcrLine {
+            bounding_box: BoundingBox::new(0, 5, 45, 10, 50, 50),
+            words: vec![OcrWord { text: "hello".to_string(), bounding_box: BoundingBox::new(0, 5, 20, 10, 50, 50), confidence: Some(0.9) }],
+        }]);
+        let mapped = scaled.to_frame_coordinates(&info);
+        let first = mapped.words().next().unwrap();
+        assert_eq!((first.bounding_box.left, first.bounding_box.top), (100, 10));
+        assert_eq!((first.bounding_box.width, first.bounding_box.height), (40, 20));
+        assert_eq!(mapped.to_text(true), "(0.50, 0.10) hello");
+
+        let json = serde_json::to_string(&result).unwrap();
+        assert_eq!(serde_json::from_str::<OcrResult>(&json).unwrap(), result);
+    }
+
+    #[test]
+    fn test_ocr_confidence_filtering() {
+        let line = |words: Vec<OcrWord>| OcrLine {
+            bounding_box: BoundingBox::union(words.iter().map(|word| &word.bounding_box), 100, 100).unwrap(),
+            words,
+        };
+        let mut result = OcrResult::from_lines(100, 100, vec![
+            line(vec![word("invoice", 0, Some(0.9)), word("|||", 60, Some(0.2))]),
+            line(vec![word("~.", 0, Some(0.4)), word("ok", 50, Some(0.5))]),
+            line(vec![word("native", 0, None)]),
+        ]);
+        // Weighted by characters: (7 * 0.9 + 3 * 0.2 + 2 * 0.4 + 2 * 0.5) / 14
+        assert!((result.mean_confidence().unwrap() - 0.6214).abs() < 1e-3);
+        assert_eq!(result.confidence, None);
+
+        result.retain_confident(0.3, 0.6);
+        assert_eq!(result.text(), "invoice native");
+        assert_eq!(result.dropped_words, 3);
+        assert_eq!(result.lines().next().unwrap().bounding_box.width, 40);
+        // The aggregate keeps the words that were dropped
+        assert!((result.confidence.unwrap() - 0.6214).abs() < 1e-3);
+        assert!((result.mean_confidence().unwrap() - 0.9).abs() < 1e-6);
+
+        let mut unsure = OcrResult::from_lines(100, 100, vec![line(vec![word("|||", 0, Some(0.2))])]);
+        unsure.retain_confident(0.5, 0.0);
+        assert!(unsure.is_empty());
+        assert!((unsure.confidence.unwrap() - 0.2).abs() < 1e-6);
+
+        assert!(OcrConfig::default().with_min_confidence(Some(1.5), None).validate().is_err());
+    }
+
+    #[test]
+    fn test_preprocess_dark_theme() {
+        // Light gray "text" on a dark background
+        let mut dark = image::RgbImage::from_pixel(40, 20, image::Rgb([30, 30, 30]));
+        for x in 10..30 {
+            for y in 8..12 {
+                dark.put_pixel(x, y, image::Rgb([200, 200, 200]));
+            }
+        }
+        let image = image::DynamicImage::ImageRgb8(dark);
+
+        let steps: Vec<PreprocessStep> = ["grayscale", "invert", "contrast", "threshold", "denoise", "upscale"]
+            .iter()
+            .map(|step| step.parse().unwrap())
+            .collect();
+        let debug_dir = std::env::temp_dir().join("k21-it-preprocess");
+        let _ = std::fs::remove_dir_all(&debug_dir);
+        let config = PreprocessConfig::new(steps).with_dpi(Some(100), Some(300)).with_debug_dir(debug_dir.clone());
+
+        let processed = preprocess(&image, &config).unwrap().to_luma8();
+        assert_eq!(processed.dimensions(), (120, 60));
+        // Black text on white after inverting and thresholding
+        assert_eq!(processed.get_pixel(5, 5)[0], 255);
+        assert_eq!(processed.get_pixel(60, 30)[0], 0);
+        // The input and one image per step
+        assert_eq!(std::fs::read_dir(&debug_dir).unwrap().count(), 7);
+        std::fs::remove_dir_all(&debug_dir).unwrap();
+
+        assert!("sharpen".parse::<PreprocessStep>().is_err());
+
+        // Boxes found in the upscaled image map back to the original
+        let words = vec![OcrWord {
+            text: "text".into(),
+            bounding_box: BoundingBox::new(30, 24, 60, 12, 120, 60),
+            confidence: None,
+        }];
+        let bounding_box = words[0].bounding_box;
+        let result = OcrResult::from_lines(120, 60, vec![OcrLine { bounding_box, words }]).scaled_to(40, 20);
+        let word = result.words().next().unwrap();
+        assert_eq!((word.bounding_box.left, word.bounding_box.top, word.bounding_box.width), (10, 8, 20));
+    }
+
+    /// Engine that "reads" a fixed word across the whole image.
+    struct FixedEngine;
+
+    #[async_trait::async_trait]
+    impl OcrEngine for FixedEngine {
+        fn name(&self) -> &str {
+            "it-fixed"
+        }
+
+        async fn recognize(&self, image: &image::DynamicImage, _config: &OcrConfig) -> anyhow::Result<OcrResult> {
+            let (width, height) = (image.width(), image.height());
+            let words = vec![
+                word("fixed", 0, Some(0.9)),
+                OcrWord {
+                    text: "~".into(),
+                    bounding_box: BoundingBox::new(0, 50, 10, 10, width, height),
+                    confidence: Some(0.1),
+                },
+            ];
+            let bounding_box = BoundingBox::union(words.iter().map(|word| &word.bounding_box), width, height).unwrap();
+            Ok(OcrResult::from_lines(width, height, vec![OcrLine { bounding_box, words }]))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_ocr_engine() {
+        register_ocr_engine(std::sync::Arc::new(FixedEngine));
+        let image = image::DynamicImage::new_rgb8(100, 100);
+
+        let config = OcrConfig::default().with_engine("it-fixed").with_min_confidence(Some(0.5), None);
+        assert!(config.validate().is_ok());
+        let mut plain = config.clone();
+        plain.bounding_boxes = Some(false);
+        assert_eq!(process_ocr(&image, &plain).await.unwrap(), "fixed");
+
+        let error = OcrConfig::default().with_engine("missing").validate().unwrap_err();
+        assert!(error.to_string().contains("it-fixed"), "{}", error);
+        assert!(process_ocr(&image, &OcrConfig::default().with_engine("missing")).await.is_err());
+    }
+
+    #[test]
+    fn test_tesseract_config_validation() {
+        let tessdata = std::env::temp_dir().join("k21-it-tessdata");
+        std::fs::create_dir_all(&tessdata).unwrap();
+        std::fs::write(tessdata.join("eng.traineddata"), b"").unwrap();
+
+        let config = OcrConfig::new(OcrModel::Tesseract, None, None, None, None)
+            .with_tessdata_path(tessdata.clone())
+            .with_char_whitelist("0123456789");
+        assert_eq!(config.get_languages(), vec!["eng".to_string()]);
+        assert!(config.validate().is_ok());
+
+        let error = config.clone().with_languages(vec!["eng".into(), "deu".into()]).validate().unwrap_err();
+        assert!(error.to_string().contains("deu.traineddata"), "{}", error);
+
+        let error = config.clone().with_user_words(tessdata.join("missing.txt")).validate().unwrap_err();
+        assert!(error.to_string().contains("missing.txt"), "{}", error);
+
+        assert!(config.with_config_variable("bad key", "1").validate().is_err());
+        std::fs::remove_dir_all(&tessdata).unwrap();
+    }
 }
 
 mod upload_tests {